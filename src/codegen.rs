@@ -0,0 +1,234 @@
+//! Generate Rust structs deserializing the JSON objects described by possible types
+
+use crate::possible_types::*;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Rust keywords and reserved words that cannot be used as field names without the `r#` prefix
+const RUST_KEYWORDS: [&str; 47] = [
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+/// Keywords that cannot be raw identifiers, and get a `_` suffix instead
+const NON_RAW_KEYWORDS: [&str; 4] = ["crate", "self", "super", "Self"];
+
+/// Generate the Rust code of a `#[derive(Deserialize)]` struct named `name`,
+/// deserializing the objects described by the possible types.
+/// Nullable or missing fields are wrapped in an `Option`, and nested objects get their own struct,
+/// named after the struct containing them and their field name.
+pub fn generate_rust_struct(possible_types: &PossibleTypes, name: &str) -> String {
+    let mut code = String::new();
+    code += "use serde::Deserialize;\n";
+    let mut struct_names = HashSet::new();
+    struct_names.insert(name.to_string());
+    generate_struct(possible_types, name, &mut struct_names, &mut code);
+    code
+}
+
+/// Generate the code of a struct, followed by the code of the structs of its fields.
+/// `struct_names` contains the names already used by the structs, which are all made unique.
+fn generate_struct(possible_types: &PossibleTypes, name: &str, struct_names: &mut HashSet<String>, code: &mut String) {
+    let n_objects = possible_types.n_type(ValueType::Object);
+    let mut fields: Vec<_> = possible_types.possible_fields.iter().collect();
+    fields.sort_by_key(|(field_name, _)| *field_name);
+
+    let mut nested_structs = vec![];
+    let mut identifiers = HashSet::new();
+    writeln!(code, "\n#[derive(Deserialize, Debug, Clone)]").unwrap();
+    writeln!(code, "pub struct {} {{", name).unwrap();
+    for (field_name, field) in fields {
+        let (identifier, renamed) = field_identifier(field_name);
+        // Different field names can give the same identifier, like `a-b` and `a_b`
+        let unique = unique_name(&identifier, "_", &mut identifiers);
+        let renamed = renamed || unique != identifier;
+        let identifier = unique;
+        let struct_name = format!("{}{}", name, pascal_case(field_name));
+        let rust_type = rust_type(field, &struct_name, struct_names, &mut nested_structs);
+        let rust_type = if field.is_nullable(n_objects) {
+            format!("Option<{}>", rust_type)
+        } else {
+            rust_type
+        };
+        if renamed {
+            writeln!(code, "    #[serde(rename = {:?})]", field_name).unwrap();
+        }
+        writeln!(code, "    pub {}: {},", identifier, rust_type).unwrap();
+    }
+    writeln!(code, "}}").unwrap();
+
+    for (possible_types, struct_name) in nested_structs {
+        generate_struct(possible_types, &struct_name, struct_names, code);
+    }
+}
+
+/// Make a name unique among the used names by adding a number to it, and add it to the used names
+fn unique_name(name: &str, separator: &str, used_names: &mut HashSet<String>) -> String {
+    let mut unique = name.to_string();
+    let mut counter = 1;
+    while !used_names.insert(unique.clone()) {
+        counter += 1;
+        unique = format!("{}{}{}", name, separator, counter);
+    }
+    unique
+}
+
+/// Get the Rust type of a value, without considering the null values.
+/// The objects found are added to `nested_structs`, along their struct name,
+/// which is made unique among `struct_names`.
+fn rust_type<'a>(
+    possible_types: &'a PossibleTypes,
+    struct_name: &str,
+    struct_names: &mut HashSet<String>,
+    nested_structs: &mut Vec<(&'a PossibleTypes, String)>,
+) -> String {
+    let mut types: Vec<_> = possible_types
+        .type_counts
+        .keys()
+        .copied()
+        .filter(|t| *t != ValueType::Null)
+        .collect();
+    types.sort();
+    match types[..] {
+        [ValueType::Bool] => "bool".to_string(),
+        [ValueType::Number] if possible_types.n_floats > 0 => "f64".to_string(),
+        [ValueType::Number] => "i64".to_string(),
+        [ValueType::String] => "String".to_string(),
+        [ValueType::Array] => match &possible_types.array_elements {
+            Some(elements) if elements.n_present() > 0 => {
+                let element_type =
+                    rust_type(elements, &format!("{}Element", struct_name), struct_names, nested_structs);
+                if elements.n_type(ValueType::Null) > 0 {
+                    format!("Vec<Option<{}>>", element_type)
+                } else {
+                    format!("Vec<{}>", element_type)
+                }
+            }
+            _ => "Vec<serde_json::Value>".to_string(),
+        },
+        [ValueType::Object] if possible_types.possible_fields.is_empty() => {
+            "std::collections::HashMap<String, serde_json::Value>".to_string()
+        }
+        [ValueType::Object] => {
+            let struct_name = unique_name(struct_name, "", struct_names);
+            nested_structs.push((possible_types, struct_name.clone()));
+            struct_name
+        }
+        // Fields that are always null, or that take different types
+        _ => "serde_json::Value".to_string(),
+    }
+}
+
+/// Get a Rust identifier for a JSON field name.
+/// Also return true if the field needs to be renamed with `#[serde(rename)]`.
+fn field_identifier(field_name: &str) -> (String, bool) {
+    let mut identifier: String = field_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    if NON_RAW_KEYWORDS.contains(&identifier.as_str()) || identifier == "_" {
+        identifier.push('_');
+    }
+    let renamed = identifier != field_name;
+    if RUST_KEYWORDS.contains(&identifier.as_str()) {
+        return (format!("r#{}", identifier), renamed);
+    }
+    (identifier, renamed)
+}
+
+/// Convert a JSON field name into a PascalCase struct name
+fn pascal_case(field_name: &str) -> String {
+    field_name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_nested_struct() {
+        let mut possible_types = PossibleTypes::new();
+        for line in [
+            r#"{"id":"a","score":1,"media":{"type":"youtube.com","oembed":{"width":600}},"tags":["x"],"ratio":0.5}"#,
+            r#"{"id":"b","score":2,"media":null,"tags":[],"ratio":1,"edited":false}"#,
+        ]
+        .iter()
+        {
+            possible_types.add_type(serde_json::from_str(line).unwrap());
+        }
+        let code = generate_rust_struct(&possible_types, "Post");
+        let expected = r#"use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Post {
+    pub edited: Option<bool>,
+    pub id: String,
+    pub media: Option<PostMedia>,
+    pub ratio: f64,
+    pub score: i64,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PostMedia {
+    pub oembed: PostMediaOembed,
+    pub r#type: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PostMediaOembed {
+    pub width: i64,
+}
+"#;
+        assert_eq!(code, expected);
+        assert_eq!(field_identifier("author-flair"), ("author_flair".to_string(), true));
+        assert_eq!(field_identifier("Type"), ("r#type".to_string(), true));
+        assert_eq!(field_identifier("crate"), ("crate_".to_string(), true));
+    }
+
+    /// Generate valid and distinct identifiers for the fields whose names are keywords or collide
+    #[test]
+    fn generate_unique_identifiers() {
+        let mut possible_types = PossibleTypes::new();
+        let line = r#"{"Type":1,"self":true,"crate":"c","Self":2,"_":3,"-":{"x":1},"a-b":{"c":{"d":1}},"a_b":{"c":[{"e":1}]},"a_b_c":{"d":"x"},"2x":0.5}"#;
+        possible_types.add_type(serde_json::from_str(line).unwrap());
+        let code = generate_rust_struct(&possible_types, "Post");
+        let structs: Vec<_> = code.lines().filter_map(|line| line.strip_prefix("pub struct ")).collect();
+        assert_eq!(structs, vec!["Post {", "Post2 {", "PostAB {", "PostABC2 {", "PostAB2 {", "PostAB2CElement {", "PostABC {"]);
+        let fields: Vec<_> = code
+            .lines()
+            .skip_while(|line| *line != "pub struct Post {")
+            .skip(1)
+            .take_while(|line| *line != "}")
+            .filter_map(|line| line.trim_start().strip_prefix("pub "))
+            .collect();
+        let expected_fields = vec![
+            "__: Post2,",
+            "_2x: f64,",
+            "self_: i64,",
+            "r#type: i64,",
+            "___2: i64,",
+            "a_b: PostAB,",
+            "a_b_2: PostAB2,",
+            "a_b_c: PostABC,",
+            "crate_: String,",
+            "self__2: bool,",
+        ];
+        assert_eq!(fields, expected_fields);
+        assert!(code.contains("    #[serde(rename = \"self\")]\n    pub self__2: bool,\n"));
+    }
+}
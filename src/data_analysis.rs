@@ -1,21 +1,49 @@
 //! Contains different functions that can give interesting information
 
 #![allow(dead_code)]
+use crate::reddit_comment::*;
 use crate::reddit_post::*;
 use crate::subreddit_stats::*;
+use crate::url_keys::UrlKeyer;
 use serde::{Deserialize, Serialize};
 pub use std::collections::{HashMap, HashSet};
 use std::io::prelude::*;
 
-/// Structure representing posts grouped by urls
+/// Structure representing posts grouped by urls.
+/// The urls are the keys computed by an `UrlKeyer`.
+/// If they are split, the crossposts are not grouped by their url, but by the id of their parent submission.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SubredditsFromUrls {
-    pub urls: HashMap<String, Vec<(usize, i32)>>,
+    pub urls: HashMap<String, Vec<(usize, i64)>>,
     pub subreddits: Vec<String>,
+    #[serde(default)]
+    pub crossposts: HashMap<String, CrosspostFamily>,
+    /// Ids of the posts of the subreddits given to `get_urls`, if the crossposts are split
+    #[serde(skip)]
+    pub post_ids: HashSet<String>,
 }
 
-/// Get posts associated with urls
-pub fn get_urls<IT>(iterator: IT, subreddits: Option<&HashSet<String>>) -> SubredditsFromUrls
+/// A submission and the subreddits it was crossposted to
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CrosspostFamily {
+    /// Subreddit of the parent submission, if it was found by `link_crosspost_parents`
+    pub parent: Option<usize>,
+    pub crossposts: Vec<usize>,
+}
+
+/// Get the id of a subreddit, adding it to the subreddits if it is not present
+fn get_subreddit_id(subreddit: &str, subreddits: &mut Vec<String>, subreddit_to_int: &mut HashMap<String, usize>) -> usize {
+    if let Some(id) = subreddit_to_int.get(subreddit) {
+        return *id;
+    }
+    subreddit_to_int.insert(subreddit.to_string(), subreddits.len());
+    subreddits.push(subreddit.to_string());
+    subreddits.len() - 1
+}
+
+/// Get posts associated with urls.
+/// If split_crossposts is true, the crossposts are grouped by their parent instead of their urls.
+pub fn get_urls<IT>(iterator: IT, subreddits: Option<&HashSet<String>>, keyer: &UrlKeyer, split_crossposts: bool) -> SubredditsFromUrls
 where
     IT: Iterator<Item = RedditPost>,
 {
@@ -30,35 +58,45 @@ where
         .map(|(i, s)| (s, i))
         .collect();
     let mut map = HashMap::new();
+    let mut crossposts = HashMap::<String, CrosspostFamily>::new();
+    let mut post_ids = HashSet::new();
     for post in iterator {
         if let Some(subreddits) = &subreddits {
             if !subreddits.contains(&post.subreddit) {
                 continue;
             }
         }
-        let url = post.get_linked_url();
-        if let Some(url) = url {
-            if !subreddit_to_int.contains_key(&post.subreddit) {
-                subreddit_to_int.insert(post.subreddit.clone(), subreddits_vec.len());
-                subreddits_vec.push(post.subreddit.clone());
-            }
-            let subreddit_id = *subreddit_to_int.get(&post.subreddit).unwrap();
-            if !map.contains_key(&url) {
-                map.insert(url.to_string(), vec![]);
-            }
-            map.get_mut(&url)
-                .unwrap()
+        if split_crossposts {
+            post_ids.insert(post.id.clone());
+        }
+        if let Some(parent_id) = post.get_crosspost_parent_id().filter(|_| split_crossposts) {
+            let subreddit_id = get_subreddit_id(&post.subreddit, &mut subreddits_vec, &mut subreddit_to_int);
+            crossposts
+                .entry(parent_id.to_string())
+                .or_default()
+                .crossposts
+                .push(subreddit_id);
+            continue;
+        }
+        for url in keyer.post_keys(&post) {
+            let subreddit_id = get_subreddit_id(&post.subreddit, &mut subreddits_vec, &mut subreddit_to_int);
+            map.entry(url)
+                .or_insert_with(Vec::new)
                 .push((subreddit_id, post.created_utc));
         }
     }
     SubredditsFromUrls {
         urls: map,
         subreddits: subreddits_vec,
+        crossposts,
+        post_ids,
     }
 }
 
-/// Get posts associated with the urls given as input
-pub fn get_posts_with_urls<IT>(iterator: IT, urls: &mut SubredditsFromUrls)
+/// Get posts associated with the urls given as input.
+/// If split_crossposts is true, the crossposts are grouped by their parent instead of their urls,
+/// and only the crossposts of the posts found by `get_urls` are kept.
+pub fn get_posts_with_urls<IT>(iterator: IT, urls: &mut SubredditsFromUrls, keyer: &UrlKeyer, split_crossposts: bool)
 where IT: Iterator<Item = RedditPost>
 {
     let mut subreddit_to_int: HashMap<_,_> = urls.subreddits.iter().enumerate().map(|(i,s)| (s.to_string(),i)).collect();
@@ -68,52 +106,83 @@ where IT: Iterator<Item = RedditPost>
         if original_subreddits.contains(&post.subreddit) {
             continue;
         }
-        let url = post.get_linked_url();
-        if let Some(url) = url {
-            if !urls.urls.contains_key(&url) {
+        if let Some(parent_id) = post.get_crosspost_parent_id().filter(|_| split_crossposts) {
+            if !urls.post_ids.contains(parent_id) {
                 continue;
             }
-            if !subreddit_to_int.contains_key(&post.subreddit) {
-                subreddit_to_int.insert(post.subreddit.clone(), urls.subreddits.len());
-                urls.subreddits.push(post.subreddit.clone());
+            let subreddit_id = get_subreddit_id(&post.subreddit, &mut urls.subreddits, &mut subreddit_to_int);
+            urls.crossposts
+                .entry(parent_id.to_string())
+                .or_default()
+                .crossposts
+                .push(subreddit_id);
+            continue;
+        }
+        for url in keyer.post_keys(&post) {
+            if !urls.urls.contains_key(&url) {
+                continue;
             }
-            let subreddit_id = *subreddit_to_int.get(&post.subreddit).unwrap();
+            let subreddit_id = get_subreddit_id(&post.subreddit, &mut urls.subreddits, &mut subreddit_to_int);
             urls.urls.get_mut(&url).unwrap().push((subreddit_id, post.created_utc));
         }
     }
 }
 
+/// Find the subreddits of the parent submissions of the crossposts
+pub fn link_crosspost_parents<IT>(iterator: IT, urls: &mut SubredditsFromUrls)
+where IT: Iterator<Item = RedditPost>
+{
+    let mut subreddit_to_int: HashMap<_,_> = urls.subreddits.iter().enumerate().map(|(i,s)| (s.to_string(),i)).collect();
+    for post in iterator {
+        if let Some(family) = urls.crossposts.get_mut(&post.id) {
+            family.parent = Some(get_subreddit_id(&post.subreddit, &mut urls.subreddits, &mut subreddit_to_int));
+        }
+    }
+}
+
 /// Reposts stats for a subreddit
 #[derive(Clone, Debug)]
 pub struct RepostStats {
-    pub n_posts: u32,
-    pub n_reposts_from_others: u32,
-    pub n_reposted_by_others: u32,
-    pub n_reposts_from_self: u32,
-    pub reposts_from_others: HashMap<usize, u32>,
-    pub reposts_by_others: HashMap<usize, u32>,
+    pub n_posts: u64,
+    pub n_reposts_from_others: u64,
+    pub n_reposted_by_others: u64,
+    pub n_reposts_from_self: u64,
+    pub reposts_from_others: HashMap<usize, u64>,
+    pub reposts_by_others: HashMap<usize, u64>,
+    /// Crossposts of submissions of other subreddits
+    pub n_crossposts_from_others: u64,
+    /// Crossposts of submissions of the subreddit by other subreddits
+    pub n_crossposted_by_others: u64,
+    /// Crossposts whose parent submission was not found in the datasets
+    pub n_crossposts_from_unknown: u64,
+    pub crossposts_from_others: HashMap<usize, u64>,
+    pub crossposts_by_others: HashMap<usize, u64>,
+}
+
+/// Sort the counts of the subreddits, and keep only the n_samples largest ones
+fn most_common(counts: HashMap<usize, u64>, n_samples: usize) -> Vec<(usize, u64)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(_,i1), (_,i2)| {
+        i2.cmp(i1)
+    });
+    counts.into_iter().take(n_samples).collect()
 }
 
 impl RepostStats {
     /// Sort the reposts_* fields, and keep only the most significants elements
     pub fn sort(self, n_samples: usize) -> RepostStatsSorted {
-        let mut reposts_from_others: Vec<_> = self.reposts_from_others.into_iter().collect();
-        reposts_from_others.sort_by(|(_,i1), (_,i2)| {
-            i2.cmp(&i1)
-        });
-        let reposts_from_others = reposts_from_others.into_iter().take(n_samples).collect();
-        let mut reposts_by_others: Vec<_> = self.reposts_by_others.into_iter().collect();
-        reposts_by_others.sort_by(|(_,i1), (_,i2)| {
-            i2.cmp(&i1)
-        });
-        let reposts_by_others = reposts_by_others.into_iter().take(n_samples).collect();
         RepostStatsSorted {
             n_posts: self.n_posts,
             n_reposts_from_others: self.n_reposts_from_others,
             n_reposted_by_others: self.n_reposted_by_others,
             n_reposts_from_self: self.n_reposts_from_self,
-            reposts_from_others,
-            reposts_by_others,
+            reposts_from_others: most_common(self.reposts_from_others, n_samples),
+            reposts_by_others: most_common(self.reposts_by_others, n_samples),
+            n_crossposts_from_others: self.n_crossposts_from_others,
+            n_crossposted_by_others: self.n_crossposted_by_others,
+            n_crossposts_from_unknown: self.n_crossposts_from_unknown,
+            crossposts_from_others: most_common(self.crossposts_from_others, n_samples),
+            crossposts_by_others: most_common(self.crossposts_by_others, n_samples),
         }
     }
 }
@@ -121,37 +190,53 @@ impl RepostStats {
 /// Reposts stats for a subreddit, where the reposts_* fields are sorted
 #[derive(Clone, Debug)]
 pub struct RepostStatsSorted {
-    pub n_posts: u32,
-    pub n_reposts_from_others: u32,
-    pub n_reposted_by_others: u32,
-    pub n_reposts_from_self: u32,
-    pub reposts_from_others: Vec<(usize, u32)>,
-    pub reposts_by_others: Vec<(usize, u32)>,
+    pub n_posts: u64,
+    pub n_reposts_from_others: u64,
+    pub n_reposted_by_others: u64,
+    pub n_reposts_from_self: u64,
+    pub reposts_from_others: Vec<(usize, u64)>,
+    pub reposts_by_others: Vec<(usize, u64)>,
+    pub n_crossposts_from_others: u64,
+    pub n_crossposted_by_others: u64,
+    pub n_crossposts_from_unknown: u64,
+    pub crossposts_from_others: Vec<(usize, u64)>,
+    pub crossposts_by_others: Vec<(usize, u64)>,
 }
 
 impl RepostStatsSorted {
     pub fn display(self, subreddits: Vec<String>) -> RepostStatsSortedDisplay {
-        let reposts_from_others = self.reposts_from_others.into_iter().map(|(s,i)| (subreddits[s].to_string(), i)).collect();
-        let reposts_by_others = self.reposts_by_others.into_iter().map(|(s,i)| (subreddits[s].to_string(), i)).collect();
+        let names = |counts: Vec<(usize, u64)>| -> Vec<(String, u64)> {
+            counts.into_iter().map(|(s,i)| (subreddits[s].to_string(), i)).collect()
+        };
         RepostStatsSortedDisplay {
             n_posts: self.n_posts,
             n_reposts_from_others: self.n_reposts_from_others,
             n_reposted_by_others: self.n_reposted_by_others,
             n_reposts_from_self: self.n_reposts_from_self,
-            reposts_from_others,
-            reposts_by_others,
+            reposts_from_others: names(self.reposts_from_others),
+            reposts_by_others: names(self.reposts_by_others),
+            n_crossposts_from_others: self.n_crossposts_from_others,
+            n_crossposted_by_others: self.n_crossposted_by_others,
+            n_crossposts_from_unknown: self.n_crossposts_from_unknown,
+            crossposts_from_others: names(self.crossposts_from_others),
+            crossposts_by_others: names(self.crossposts_by_others),
         }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct RepostStatsSortedDisplay {
-    pub n_posts: u32,
-    pub n_reposts_from_others: u32,
-    pub n_reposted_by_others: u32,
-    pub n_reposts_from_self: u32,
-    pub reposts_from_others: Vec<(String, u32)>,
-    pub reposts_by_others: Vec<(String, u32)>,
+    pub n_posts: u64,
+    pub n_reposts_from_others: u64,
+    pub n_reposted_by_others: u64,
+    pub n_reposts_from_self: u64,
+    pub reposts_from_others: Vec<(String, u64)>,
+    pub reposts_by_others: Vec<(String, u64)>,
+    pub n_crossposts_from_others: u64,
+    pub n_crossposted_by_others: u64,
+    pub n_crossposts_from_unknown: u64,
+    pub crossposts_from_others: Vec<(String, u64)>,
+    pub crossposts_by_others: Vec<(String, u64)>,
 }
 
 /// Get reposts statistics for a subreddit
@@ -200,6 +285,28 @@ pub fn get_reposts_stats(subreddit: &str, urls: &SubredditsFromUrls) -> RepostSt
         }
     }
 
+    let mut n_crossposts_from_others = 0;
+    let mut n_crossposted_by_others = 0;
+    let mut n_crossposts_from_unknown = 0;
+    let mut crossposts_from_others = HashMap::new();
+    let mut crossposts_by_others = HashMap::new();
+    for family in urls.crossposts.values() {
+        for crosspost in family.crossposts.iter() {
+            match family.parent {
+                None if *crosspost == subreddit_id => n_crossposts_from_unknown += 1,
+                Some(parent) if *crosspost == subreddit_id && parent != subreddit_id => {
+                    n_crossposts_from_others += 1;
+                    *crossposts_from_others.entry(parent).or_insert(0) += 1;
+                }
+                Some(parent) if parent == subreddit_id && *crosspost != subreddit_id => {
+                    n_crossposted_by_others += 1;
+                    *crossposts_by_others.entry(*crosspost).or_insert(0) += 1;
+                }
+                _ => (),
+            }
+        }
+    }
+
     RepostStats {
         n_posts,
         n_reposts_from_self,
@@ -207,13 +314,92 @@ pub fn get_reposts_stats(subreddit: &str, urls: &SubredditsFromUrls) -> RepostSt
         n_reposts_from_others,
         reposts_from_others,
         reposts_by_others,
+        n_crossposts_from_others,
+        n_crossposted_by_others,
+        n_crossposts_from_unknown,
+        crossposts_from_others,
+        crossposts_by_others,
+    }
+}
+
+/// Get the ids of the posts linking to one of the given urls.
+/// The id is associated to true if the post is the first one to link to one of its urls,
+/// and to false if it is a repost. The crossposts are ignored.
+pub fn get_posts_roles<IT>(iterator: IT, urls: &SubredditsFromUrls, keyer: &UrlKeyer) -> HashMap<String, bool>
+where
+    IT: Iterator<Item = RedditPost>,
+{
+    let mut posts_by_url = HashMap::new();
+    for post in iterator.filter(|post| post.crosspost_parent.is_none()) {
+        for url in keyer.post_keys(&post) {
+            if urls.urls.contains_key(&url) {
+                posts_by_url
+                    .entry(url)
+                    .or_insert_with(Vec::new)
+                    .push((post.created_utc, post.id.clone()));
+            }
+        }
+    }
+    let mut roles = HashMap::new();
+    for (_, mut posts) in posts_by_url.into_iter() {
+        posts.sort();
+        for (i, (_, id)) in posts.into_iter().enumerate() {
+            *roles.entry(id).or_insert(false) |= i == 0;
+        }
+    }
+    roles
+}
+
+/// Count the comments posted in the given posts.
+/// The comments are joined with their post through their `link_id` field.
+pub fn count_comments_per_post<IT>(iterator: IT, posts: &HashMap<String, bool>) -> HashMap<String, u64>
+where
+    IT: Iterator<Item = RedditComment>,
+{
+    let mut n_comments = HashMap::new();
+    for comment in iterator {
+        let post_id = comment.get_submission_id();
+        if posts.contains_key(post_id) {
+            *n_comments.entry(post_id.to_string()).or_insert(0) += 1;
+        }
     }
+    n_comments
+}
+
+/// Number of comments received by the posts that first linked to an url,
+/// compared to the number of comments received by their reposts
+#[derive(Clone, Debug, Default)]
+pub struct DiscussionStats {
+    pub n_original_posts: u64,
+    pub n_comments_on_originals: u64,
+    pub n_reposts: u64,
+    pub n_comments_on_reposts: u64,
+}
+
+/// Get the discussion stats, given the roles of the posts computed by `get_posts_roles`,
+/// and their number of comments computed by `count_comments_per_post`
+pub fn get_discussion_stats(
+    posts: &HashMap<String, bool>,
+    n_comments: &HashMap<String, u64>,
+) -> DiscussionStats {
+    let mut stats = DiscussionStats::default();
+    for (id, is_original) in posts.iter() {
+        let n_comments = *n_comments.get(id).unwrap_or(&0);
+        if *is_original {
+            stats.n_original_posts += 1;
+            stats.n_comments_on_originals += n_comments;
+        } else {
+            stats.n_reposts += 1;
+            stats.n_comments_on_reposts += n_comments;
+        }
+    }
+    stats
 }
 
 /// Struct representing the number of urls shared between subreddits
 pub struct UrlsBetweenSubreddits {
     subreddits: Vec<String>,
-    n_shared_urls: HashMap<usize, HashMap<usize, u32>>,
+    n_shared_urls: HashMap<usize, HashMap<usize, u64>>,
 }
 
 /// Get the number of shared between subreddits.
@@ -259,7 +445,7 @@ pub fn compute_ppmi(urls: UrlsBetweenSubreddits) -> UrlsPPMI {
     let sum_col: HashMap<_, _> = urls
         .n_shared_urls
         .iter()
-        .map(|(s, hm)| (s.clone(), hm.iter().fold(0f32, |s, (_, i)| s + *i as f32)))
+        .map(|(s, hm)| (*s, hm.iter().fold(0f32, |s, (_, i)| s + *i as f32)))
         .collect();
     let sum_all = sum_col.iter().fold(0f32, |s, (_, i)| s + i);
     let matrix = urls
@@ -328,3 +514,51 @@ pub fn write_ppmi_for_python_plot(
         buf_writer.write_all("\n".as_bytes()).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(id: &str, subreddit: &str, url: &str, created_utc: i64, crosspost_parent: Option<&str>) -> RedditPost {
+        RedditPost {
+            crosspost_parent: crosspost_parent.map(str::to_string),
+            ..test_post(id, subreddit, url, created_utc)
+        }
+    }
+
+    /// The crossposts are linked to their parent, and counted apart from the url reposts
+    #[test]
+    fn crossposts_stats() {
+        let posts = vec![
+            post("a", "pics", "https://example.com/1", 1, None),
+            post("b", "funny", "https://example.com/1", 2, None),
+            post("c", "funny", "https://example.com/1", 3, Some("t3_a")),
+            post("d", "aww", "/r/funny/comments/e/t/", 4, Some("t3_e")),
+            post("e", "funny", "/r/funny/comments/e/t/", 0, None),
+            post("f", "pics", "https://example.com/2", 5, None),
+            post("g", "funny", "https://example.com/2", 6, Some("t3_f")),
+            post("h", "funny", "https://example.com/3", 7, Some("t3_missing")),
+            post("i", "aww", "https://example.com/4", 8, Some("t3_j")),
+            post("j", "news", "https://example.com/4", 0, None),
+        ];
+        let keyer = UrlKeyer::default();
+        let funny: HashSet<_> = vec!["funny".to_string()].into_iter().collect();
+        let unsplit_urls = get_urls(posts.clone().into_iter(), Some(&funny), &keyer, false);
+        assert_eq!(unsplit_urls.urls["https://example.com/1"].len(), 2);
+        assert!(unsplit_urls.crossposts.is_empty());
+
+        let mut urls = get_urls(posts.clone().into_iter(), Some(&funny), &keyer, true);
+        get_posts_with_urls(posts.clone().into_iter(), &mut urls, &keyer, true);
+        link_crosspost_parents(posts.into_iter(), &mut urls);
+        assert!(!urls.crossposts.contains_key("j"));
+
+        let stats = get_reposts_stats("funny", &urls).sort(10).display(urls.subreddits.clone());
+        assert_eq!(stats.n_reposts_from_others, 1);
+        assert_eq!(stats.reposts_from_others, vec![("pics".to_string(), 1)]);
+        assert_eq!(stats.n_crossposts_from_others, 2);
+        assert_eq!(stats.crossposts_from_others, vec![("pics".to_string(), 2)]);
+        assert_eq!(stats.n_crossposted_by_others, 1);
+        assert_eq!(stats.crossposts_by_others, vec![("aww".to_string(), 1)]);
+        assert_eq!(stats.n_crossposts_from_unknown, 1);
+    }
+}
@@ -0,0 +1,295 @@
+//! Formats of the simplified datasets, with a writer for each format,
+//! and an iterator reading the datasets whatever their format
+
+use crate::read_files::open_file;
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::prelude::*;
+use std::io::{BufWriter, Cursor, ErrorKind};
+use std::marker::PhantomData;
+
+/// First bytes of the binary datasets.
+/// The last character is the version of the record layout, which should change with the
+/// fields of `RedditPost` and `RedditComment`, since the binary records are not self-describing.
+pub const BINARY_MAGIC: &[u8] = b"RRBIN1";
+
+/// Options of the bincode encoding, with the integers encoded with a variable length
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+/// Formats of the simplified datasets
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DatasetFormat {
+    /// CSV file with a header line
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+    /// `BINARY_MAGIC`, followed by the bincode encoding of each record, prefixed by its length
+    /// as a little-endian u32. The posts are always encoded as `RedditPost`, even for the lean columns.
+    Binary,
+}
+
+impl DatasetFormat {
+    /// Get the format from its name on the command line
+    pub fn from_name(name: &str) -> Option<DatasetFormat> {
+        match name {
+            "csv" => Some(DatasetFormat::Csv),
+            "ndjson" => Some(DatasetFormat::Ndjson),
+            "binary" => Some(DatasetFormat::Binary),
+            _ => None,
+        }
+    }
+
+    /// Get the format of a dataset from its first bytes
+    pub fn detect(bytes: &[u8]) -> DatasetFormat {
+        if bytes.starts_with(BINARY_MAGIC) {
+            DatasetFormat::Binary
+        } else if bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+            DatasetFormat::Ndjson
+        } else {
+            DatasetFormat::Csv
+        }
+    }
+}
+
+/// Write records in a dataset of a given format
+pub enum RecordWriter<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Ndjson(BufWriter<W>),
+    Binary(BufWriter<W>, Vec<u8>),
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Create a writer, and write the header of the binary format
+    pub fn new(writer: W, format: DatasetFormat) -> Self {
+        match format {
+            DatasetFormat::Csv => RecordWriter::Csv(Box::new(csv::Writer::from_writer(writer))),
+            DatasetFormat::Ndjson => RecordWriter::Ndjson(BufWriter::new(writer)),
+            DatasetFormat::Binary => {
+                let mut writer = BufWriter::new(writer);
+                writer.write_all(BINARY_MAGIC).unwrap();
+                RecordWriter::Binary(writer, Vec::new())
+            }
+        }
+    }
+
+    /// Write a record
+    pub fn write<T: Serialize>(&mut self, item: &T) {
+        match self {
+            RecordWriter::Csv(writer) => writer.serialize(item).unwrap(),
+            RecordWriter::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, item).unwrap();
+                writer.write_all(b"\n").unwrap();
+            }
+            RecordWriter::Binary(writer, buffer) => {
+                buffer.clear();
+                bincode_options().serialize_into(&mut *buffer, item).unwrap();
+                writer.write_all(&(buffer.len() as u32).to_le_bytes()).unwrap();
+                writer.write_all(buffer).unwrap();
+            }
+        }
+    }
+
+    /// Write the buffered records
+    pub fn flush(&mut self) {
+        match self {
+            RecordWriter::Csv(writer) => writer.flush().unwrap(),
+            RecordWriter::Ndjson(writer) | RecordWriter::Binary(writer, _) => writer.flush().unwrap(),
+        }
+    }
+}
+
+/// Read the records of a dataset of a given format
+enum RecordReader<S: DeserializeOwned> {
+    Csv(csv::DeserializeRecordsIntoIter<Box<dyn BufRead + Send>, S>),
+    Ndjson(Box<dyn BufRead + Send>, String),
+    Binary(Box<dyn BufRead + Send>, Vec<u8>),
+}
+
+impl<S: DeserializeOwned> RecordReader<S> {
+    /// Open a dataset, detecting its format
+    fn open(filepath: &str) -> Self {
+        let mut reader = open_file(filepath);
+        let mut header = vec![0; BINARY_MAGIC.len()];
+        let header_length = read_at_most(&mut reader, &mut header);
+        header.truncate(header_length);
+        let version = BINARY_MAGIC.len() - 1;
+        if header.starts_with(&BINARY_MAGIC[..version]) && header != BINARY_MAGIC {
+            panic!("Error: {} was written with another version of the binary format, it should be simplified again", filepath);
+        }
+        let mut bytes = header.clone();
+        bytes.extend_from_slice(reader.fill_buf().unwrap());
+        let format = DatasetFormat::detect(&bytes);
+        if format == DatasetFormat::Binary {
+            return RecordReader::Binary(reader, Vec::new());
+        }
+        // The header is read again by the text formats
+        let reader: Box<dyn BufRead + Send> = Box::new(Cursor::new(header).chain(reader));
+        match format {
+            DatasetFormat::Csv => RecordReader::Csv(csv::Reader::from_reader(reader).into_deserialize()),
+            _ => RecordReader::Ndjson(reader, String::new()),
+        }
+    }
+
+    fn next(&mut self) -> Option<S> {
+        match self {
+            RecordReader::Csv(reader) => reader.next().map(Result::unwrap),
+            RecordReader::Ndjson(reader, line) => loop {
+                line.clear();
+                if reader.read_line(line).unwrap() == 0 {
+                    return None;
+                }
+                if !line.trim().is_empty() {
+                    return Some(serde_json::from_str(line).unwrap());
+                }
+            },
+            RecordReader::Binary(reader, buffer) => {
+                let mut length = [0; 4];
+                match read_at_most(reader, &mut length) {
+                    0 => return None,
+                    4 => (),
+                    _ => panic!("Error: truncated record length in binary dataset"),
+                }
+                buffer.resize(u32::from_le_bytes(length) as usize, 0);
+                reader.read_exact(buffer).expect("Error: truncated record in binary dataset");
+                Some(bincode_options().deserialize(buffer).expect("Error: invalid record in binary dataset"))
+            }
+        }
+    }
+}
+
+/// Read bytes until the buffer is full or the end of the file is reached, and get the number of bytes read
+fn read_at_most<R: Read>(reader: &mut R, buffer: &mut [u8]) -> usize {
+    let mut length = 0;
+    while length < buffer.len() {
+        match reader.read(&mut buffer[length..]) {
+            Ok(0) => break,
+            Ok(n) => length += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => panic!("{}", e),
+        }
+    }
+    length
+}
+
+/// An iterator iterating through multiple simplified datasets,
+/// to deserialize their records into a given struct.
+/// The format of each file is detected from its first bytes.
+pub struct DatasetItemIterator<S, FPI>
+where
+    FPI: Iterator<Item = String>,
+    S: DeserializeOwned,
+{
+    filepath_iterator: FPI,
+    current_reader: Option<RecordReader<S>>,
+    json_struct_type: PhantomData<S>,
+}
+
+impl<S, FPI> Clone for DatasetItemIterator<S, FPI>
+where
+    FPI: Iterator<Item = String> + Clone,
+    S: DeserializeOwned,
+{
+    fn clone(&self) -> Self {
+        assert!(self.current_reader.is_none());
+        DatasetItemIterator {
+            filepath_iterator: self.filepath_iterator.clone(),
+            current_reader: None,
+            json_struct_type: self.json_struct_type,
+        }
+    }
+}
+
+impl<S, FPI> DatasetItemIterator<S, FPI>
+where
+    FPI: Iterator<Item = String>,
+    S: DeserializeOwned,
+{
+    /// Create a new iterator, given an iterator over file paths
+    pub fn new(filepath_iterator: FPI) -> Self {
+        Self {
+            filepath_iterator,
+            current_reader: None,
+            json_struct_type: PhantomData,
+        }
+    }
+}
+
+impl<S, FPI> Iterator for DatasetItemIterator<S, FPI>
+where
+    FPI: Iterator<Item = String>,
+    S: DeserializeOwned,
+{
+    type Item = S;
+
+    fn next(&mut self) -> Option<S> {
+        loop {
+            if let Some(item) = self.current_reader.as_mut().and_then(RecordReader::next) {
+                return Some(item);
+            }
+            let filepath = self.filepath_iterator.next()?;
+            self.current_reader = Some(RecordReader::open(&filepath));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reddit_post::{test_post, RedditPost};
+    use std::fs::File;
+
+    /// Write posts in each format, and read them back in a single iterator
+    #[test]
+    fn read_all_formats() {
+        let posts: Vec<RedditPost> = (0..3)
+            .map(|i| RedditPost {
+                num_comments: i as u64,
+                score: -i,
+                title: "A title, with \"quotes\"\nand a new line".to_string(),
+                domain: Some("example.com".to_string()),
+                retrieved_on: Some(10),
+                ..test_post(&format!("p{}", i), "pics", "https://example.com", i as i64)
+            })
+            .collect();
+        let formats = [DatasetFormat::Csv, DatasetFormat::Ndjson, DatasetFormat::Binary];
+        let mut filepaths = vec![];
+        for format in formats.iter() {
+            let filepath = std::env::temp_dir().join(format!("reddit_reposts_format_{:?}", format));
+            let mut writer = RecordWriter::new(File::create(&filepath).unwrap(), *format);
+            for post in &posts {
+                writer.write(post);
+            }
+            writer.flush();
+            let mut reader = open_file(filepath.to_str().unwrap());
+            assert_eq!(DatasetFormat::detect(reader.fill_buf().unwrap()), *format);
+            filepaths.push(filepath.to_str().unwrap().to_string());
+        }
+
+        let read_posts: Vec<RedditPost> = DatasetItemIterator::new(filepaths.clone().into_iter()).collect();
+        for filepath in filepaths {
+            std::fs::remove_file(filepath).unwrap();
+        }
+        let expected: Vec<_> = formats.iter().flat_map(|_| posts.iter()).map(|post| format!("{:?}", post)).collect();
+        let read_posts: Vec<_> = read_posts.iter().map(|post| format!("{:?}", post)).collect();
+        assert_eq!(read_posts, expected);
+    }
+
+    /// Reject the binary datasets ending in the middle of a record length
+    #[test]
+    fn truncated_binary_dataset() {
+        let filepath = std::env::temp_dir().join("reddit_reposts_truncated_binary");
+        let filepaths = vec![filepath.to_str().unwrap().to_string()];
+        std::fs::write(&filepath, BINARY_MAGIC).unwrap();
+        assert_eq!(DatasetItemIterator::<RedditPost, _>::new(filepaths.clone().into_iter()).count(), 0);
+
+        let mut data = BINARY_MAGIC.to_vec();
+        data.extend_from_slice(&[1, 0]);
+        std::fs::write(&filepath, data).unwrap();
+        let result = std::panic::catch_unwind(|| DatasetItemIterator::<RedditPost, _>::new(filepaths.into_iter()).count());
+        std::fs::remove_file(filepath).unwrap();
+        assert!(result.is_err());
+    }
+}
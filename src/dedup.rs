@@ -0,0 +1,167 @@
+//! Removal of the posts present several times in overlapping or re-downloaded dumps
+
+use crate::dataset_format::{DatasetFormat, DatasetItemIterator, RecordWriter};
+use crate::filters::{filter_posts, PostFilter};
+use crate::read_files::{create_file, STDIO_PATH};
+use crate::reddit_post::RedditPost;
+use crate::simplify_dataset::ColumnSet;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Summary of a dataset deduplication, displayed by the dedup command
+#[allow(dead_code)]
+#[derive(Clone, Debug, Default)]
+pub struct DedupSummary {
+    pub n_posts: u64,
+    pub n_written: u64,
+}
+
+/// Check if a record of a post is fresher than a previous record of the same post.
+/// The records are compared by their `retrieved_on` date if they have one,
+/// otherwise the record read last is the freshest.
+fn is_fresher(retrieved_on: Option<i64>, previous_retrieved_on: Option<i64>) -> bool {
+    match (retrieved_on, previous_retrieved_on) {
+        (Some(retrieved_on), Some(previous_retrieved_on)) => retrieved_on >= previous_retrieved_on,
+        (None, Some(_)) => false,
+        _ => true,
+    }
+}
+
+/// Positions of the freshest record of each post, in a stream of posts
+#[derive(Clone, Debug, Default)]
+pub struct FreshestPosts {
+    is_freshest: Arc<Vec<bool>>,
+}
+
+impl FreshestPosts {
+    /// Find the freshest records in a first pass over the posts.
+    /// Only the ids and the retrieval dates of the posts are kept in memory.
+    pub fn find<IT, P>(iterator: IT) -> Self
+    where
+        IT: Iterator<Item = P>,
+        P: Borrow<RedditPost>,
+    {
+        let mut freshest = HashMap::<String, (Option<i64>, usize)>::new();
+        let mut is_freshest = vec![];
+        for (position, post) in iterator.enumerate() {
+            let post = post.borrow();
+            match freshest.get_mut(&post.id) {
+                Some((retrieved_on, previous_position)) => {
+                    let fresher = is_fresher(post.retrieved_on, *retrieved_on);
+                    if fresher {
+                        is_freshest[*previous_position] = false;
+                        *retrieved_on = post.retrieved_on;
+                        *previous_position = position;
+                    }
+                    is_freshest.push(fresher);
+                }
+                None => {
+                    freshest.insert(post.id.clone(), (post.retrieved_on, position));
+                    is_freshest.push(true);
+                }
+            }
+        }
+        FreshestPosts {
+            is_freshest: Arc::new(is_freshest),
+        }
+    }
+
+    /// Number of records read by `find`
+    pub fn n_posts(&self) -> usize {
+        self.is_freshest.len()
+    }
+
+    /// Keep the freshest records, in a second pass over the same posts
+    pub fn keep<IT>(&self, iterator: IT) -> impl Iterator<Item = RedditPost>
+    where
+        IT: Iterator<Item = RedditPost>,
+    {
+        let is_freshest = Arc::clone(&self.is_freshest);
+        iterator
+            .enumerate()
+            .filter(move |(position, _)| is_freshest[*position])
+            .map(|(_, post)| post)
+    }
+}
+
+/// Read the posts of simplified datasets kept by the filter, keeping the freshest record of each post, in the order of the files.
+/// The files are read twice, except the standard input, whose posts are kept in memory.
+/// The number of records kept by the filter is also returned.
+pub fn read_freshest_posts<'a>(filepaths: Vec<String>, filter: &'a PostFilter) -> (usize, Box<dyn Iterator<Item = RedditPost> + 'a>) {
+    if filepaths.iter().any(|filepath| filepath == STDIO_PATH) {
+        let posts: Vec<_> = filter_posts(DatasetItemIterator::new(filepaths.into_iter()), filter).collect();
+        let freshest = FreshestPosts::find(posts.iter());
+        return (freshest.n_posts(), Box::new(freshest.keep(posts.into_iter())));
+    }
+    let freshest = FreshestPosts::find(filter_posts(DatasetItemIterator::new(filepaths.clone().into_iter()), filter));
+    let posts = filter_posts(DatasetItemIterator::new(filepaths.into_iter()), filter);
+    (freshest.n_posts(), Box::new(freshest.keep(posts)))
+}
+
+/// Write the posts of simplified datasets in a new dataset, keeping the freshest record of each post
+pub fn dedup_datasets(filepaths: Vec<String>, new_filepath: &str, columns: ColumnSet, format: DatasetFormat) -> DedupSummary {
+    let filter = PostFilter::default();
+    let (n_posts, posts) = read_freshest_posts(filepaths, &filter);
+    let mut summary = DedupSummary {
+        n_posts: n_posts as u64,
+        n_written: 0,
+    };
+    let mut writer = RecordWriter::new(create_file(new_filepath), format);
+    for post in posts {
+        writer.write(&columns.post_record(post, format));
+        summary.n_written += 1;
+    }
+    writer.flush();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reddit_post::test_post;
+
+    /// Keep one record of each post, the one retrieved last
+    #[test]
+    fn dedup_by_id() {
+        let records = [
+            ("a", 10, Some(100)),
+            ("b", 5, None),
+            ("a", 12, Some(300)),
+            ("a", 11, Some(200)),
+            ("b", 7, None),
+            ("c", 1, Some(100)),
+            ("c", 2, None),
+        ];
+        let posts: Vec<RedditPost> = records
+            .iter()
+            .map(|(id, score, retrieved_on)| RedditPost {
+                score: *score,
+                retrieved_on: *retrieved_on,
+                ..test_post(id, "pics", "", 0)
+            })
+            .collect();
+        let freshest = FreshestPosts::find(posts.iter());
+        assert_eq!(freshest.n_posts(), 7);
+        let kept_posts: Vec<RedditPost> = freshest.keep(posts.clone().into_iter()).collect();
+        let scores: Vec<_> = kept_posts.iter().map(|post| (post.id.as_str(), post.score)).collect();
+        assert_eq!(scores, vec![("a", 12), ("b", 7), ("c", 1)]);
+
+        let filepath = std::env::temp_dir().join("reddit_reposts_dedup_input");
+        let new_filepath = std::env::temp_dir().join("reddit_reposts_dedup_output");
+        let mut writer = RecordWriter::new(std::fs::File::create(&filepath).unwrap(), DatasetFormat::Ndjson);
+        for post in &posts {
+            writer.write(post);
+        }
+        writer.flush();
+        let filepaths = vec![filepath.to_str().unwrap().to_string()];
+        let summary = dedup_datasets(filepaths, new_filepath.to_str().unwrap(), ColumnSet::Lean, DatasetFormat::Csv);
+        assert_eq!((summary.n_posts, summary.n_written), (7, 3));
+        let filepaths = vec![new_filepath.to_str().unwrap().to_string()];
+        let written_posts: Vec<RedditPost> = DatasetItemIterator::new(filepaths.into_iter()).collect();
+        std::fs::remove_file(filepath).unwrap();
+        std::fs::remove_file(new_filepath).unwrap();
+        let retrieved_on: Vec<_> = written_posts.iter().map(|post| post.retrieved_on).collect();
+        assert_eq!(retrieved_on, vec![Some(300), None, Some(100)]);
+    }
+}
@@ -0,0 +1,230 @@
+//! Tolerant deserializers for the fields whose type changed across the pushshift dumps.
+//! They are meant to be used with `#[serde(default, deserialize_with = "...")]`,
+//! so that missing fields also use their default value, except for the required fields.
+
+use serde::de::{self, Deserializer, Visitor};
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Visitor coercing numbers, floats and strings into an integer.
+/// Null values and empty strings are rejected if the integer is required.
+struct IntegerVisitor<T> {
+    required: bool,
+    integer_type: PhantomData<T>,
+}
+
+impl<T> IntegerVisitor<T>
+where
+    T: TryFrom<i64>,
+{
+    fn from_i64<E: de::Error>(value: i64) -> Result<T, E> {
+        T::try_from(value).map_err(|_| E::custom(format!("integer {} out of range", value)))
+    }
+
+    fn from_f64<E: de::Error>(value: f64) -> Result<T, E> {
+        if !value.is_finite() || value < i64::MIN as f64 || value > i64::MAX as f64 {
+            return Err(E::custom(format!("float {} out of range", value)));
+        }
+        Self::from_i64(value.trunc() as i64)
+    }
+}
+
+impl<'de, T> Visitor<'de> for IntegerVisitor<T>
+where
+    T: TryFrom<i64> + Default,
+{
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an integer, a float, a string containing a number, or null")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+        Self::from_i64(value)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+        let value = i64::try_from(value)
+            .map_err(|_| E::custom(format!("integer {} out of range", value)))?;
+        Self::from_i64(value)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<T, E> {
+        Self::from_f64(value)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        let value = value.trim();
+        if value.is_empty() {
+            return self.visit_unit();
+        }
+        if let Ok(value) = value.parse::<i64>() {
+            return Self::from_i64(value);
+        }
+        match value.parse::<f64>() {
+            Ok(value) => Self::from_f64(value),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+        }
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<T, E> {
+        if self.required {
+            Err(E::custom("missing value for a required integer"))
+        } else {
+            Ok(T::default())
+        }
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<T, E> {
+        self.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+/// Deserialize an integer that may be stored as a float or as a string.
+/// Null values and empty strings are deserialized to 0.
+pub fn integer<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64> + Default,
+{
+    deserializer.deserialize_any(IntegerVisitor {
+        required: false,
+        integer_type: PhantomData,
+    })
+}
+
+/// Deserialize an integer like `integer`, but reject the null values and empty strings.
+/// It is used without `default`, for the fields like the creation dates, where 0 is not a meaningful value.
+pub fn required_integer<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64> + Default,
+{
+    deserializer.deserialize_any(required_visitor())
+}
+
+/// Visitor coercing numbers, floats and strings into an optional integer
+struct OptionalIntegerVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for OptionalIntegerVisitor<T>
+where
+    T: TryFrom<i64> + Default,
+{
+    type Value = Option<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an integer, a float, a string containing a number, or null")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Option<T>, E> {
+        required_visitor().visit_i64(value).map(Some)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Option<T>, E> {
+        required_visitor().visit_u64(value).map(Some)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Option<T>, E> {
+        required_visitor().visit_f64(value).map(Some)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Option<T>, E> {
+        if value.trim().is_empty() {
+            return Ok(None);
+        }
+        required_visitor().visit_str(value).map(Some)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Option<T>, E> {
+        Ok(None)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Option<T>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<T>, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+/// Get a visitor rejecting the null values and empty strings
+fn required_visitor<T>() -> IntegerVisitor<T> {
+    IntegerVisitor {
+        required: true,
+        integer_type: PhantomData,
+    }
+}
+
+/// Deserialize an optional integer like `integer`.
+/// Null values and empty strings are deserialized to None.
+pub fn optional_integer<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64> + Default,
+{
+    deserializer.deserialize_any(OptionalIntegerVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Timestamp {
+        #[serde(default, deserialize_with = "super::integer")]
+        created_utc: i32,
+    }
+
+    #[derive(Deserialize)]
+    struct RequiredTimestamp {
+        #[serde(deserialize_with = "super::required_integer")]
+        created_utc: i32,
+    }
+
+    fn parse(data: &str) -> Option<i32> {
+        serde_json::from_str::<Timestamp>(data).ok().map(|t| t.created_utc)
+    }
+
+    #[derive(Deserialize)]
+    struct Crossposts {
+        #[serde(default, deserialize_with = "super::optional_integer")]
+        num_crossposts: Option<u64>,
+    }
+
+    fn parse_optional(data: &str) -> Option<Option<u64>> {
+        serde_json::from_str::<Crossposts>(data).ok().map(|c| c.num_crossposts)
+    }
+
+    fn parse_required(data: &str) -> Option<i32> {
+        serde_json::from_str::<RequiredTimestamp>(data).ok().map(|t| t.created_utc)
+    }
+
+    #[test]
+    fn coerce_integers() {
+        assert_eq!(parse(r#"{"created_utc":1293952912}"#), Some(1293952912));
+        assert_eq!(parse(r#"{"created_utc":"1293952912"}"#), Some(1293952912));
+        assert_eq!(parse(r#"{"created_utc":1293952912.0}"#), Some(1293952912));
+        assert_eq!(parse(r#"{"created_utc":"1293952912.0"}"#), Some(1293952912));
+        assert_eq!(parse(r#"{"created_utc":null}"#), Some(0));
+        assert_eq!(parse(r#"{}"#), Some(0));
+        assert_eq!(parse(r#"{"created_utc":"yesterday"}"#), None);
+        assert_eq!(parse(r#"{"created_utc":1e20}"#), None);
+
+        assert_eq!(parse_required(r#"{"created_utc":"1293952912"}"#), Some(1293952912));
+        assert_eq!(parse_required(r#"{"created_utc":null}"#), None);
+        assert_eq!(parse_required(r#"{"created_utc":""}"#), None);
+        assert_eq!(parse_required(r#"{}"#), None);
+
+        assert_eq!(parse_optional(r#"{"num_crossposts":"3"}"#), Some(Some(3)));
+        assert_eq!(parse_optional(r#"{"num_crossposts":3.0}"#), Some(Some(3)));
+        assert_eq!(parse_optional(r#"{"num_crossposts":null}"#), Some(None));
+        assert_eq!(parse_optional(r#"{}"#), Some(None));
+        assert_eq!(parse_optional(r#"{"num_crossposts":-1}"#), None);
+    }
+}
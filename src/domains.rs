@@ -0,0 +1,105 @@
+//! Registrable domains of the hosts, computed with the bundled public suffix list (https://publicsuffix.org/list/)
+
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// The public suffix rules, parsed from the bundled list
+struct PublicSuffixList {
+    suffixes: HashSet<&'static str>,
+    /// Suffixes of the `*.suffix` rules
+    wildcards: HashSet<&'static str>,
+    /// Domains of the `!domain` rules
+    exceptions: HashSet<&'static str>,
+}
+
+/// Get the bundled public suffix list
+fn public_suffix_list() -> &'static PublicSuffixList {
+    static LIST: OnceLock<PublicSuffixList> = OnceLock::new();
+    LIST.get_or_init(|| {
+        let mut list = PublicSuffixList {
+            suffixes: HashSet::new(),
+            wildcards: HashSet::new(),
+            exceptions: HashSet::new(),
+        };
+        let rules = include_str!("public_suffix_list.dat")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("//"));
+        for rule in rules {
+            if let Some(domain) = rule.strip_prefix('!') {
+                list.exceptions.insert(domain);
+            } else if let Some(suffix) = rule.strip_prefix("*.") {
+                list.wildcards.insert(suffix);
+            } else {
+                list.suffixes.insert(rule);
+            }
+        }
+        list
+    })
+}
+
+impl PublicSuffixList {
+    /// Get the number of labels of the public suffix of a domain, which is its longest matching rule.
+    /// A domain with no known suffix is assumed to have a one label suffix.
+    fn suffix_labels(&self, labels: &[&str]) -> usize {
+        for start in 0..labels.len() {
+            let candidate = labels[start..].join(".");
+            if self.exceptions.contains(candidate.as_str()) {
+                return labels.len() - start - 1;
+            }
+            let wildcard_match = start + 1 < labels.len() && self.wildcards.contains(labels[start + 1..].join(".").as_str());
+            if wildcard_match || self.suffixes.contains(candidate.as_str()) {
+                return labels.len() - start;
+            }
+        }
+        1
+    }
+}
+
+/// Get the registrable domain of a host, which is its public suffix and the label before it,
+/// like `bbc.co.uk` for `news.bbc.co.uk`.
+/// The hosts that are IP addresses or public suffixes are returned unchanged.
+pub fn registrable_domain(host: &str) -> String {
+    let host = host.split(':').next().unwrap().trim_end_matches('.').to_ascii_lowercase();
+    if host.is_empty() || host.chars().all(|c| c.is_ascii_digit() || c == '.') || host.starts_with('[') {
+        return host;
+    }
+    let labels: Vec<_> = host.split('.').collect();
+    let suffix_labels = public_suffix_list().suffix_labels(&labels);
+    if suffix_labels >= labels.len() {
+        return host;
+    }
+    labels[labels.len() - suffix_labels - 1..].join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the registrable domains, with single and multi-part suffixes
+    #[test]
+    fn registrable_domains() {
+        let domains = [
+            ("example.com", "example.com"),
+            ("www.example.com", "example.com"),
+            ("a.b.example.com", "example.com"),
+            ("news.bbc.co.uk", "bbc.co.uk"),
+            ("bbc.co.uk", "bbc.co.uk"),
+            ("www.smh.com.au", "smh.com.au"),
+            ("someone.blogspot.com", "someone.blogspot.com"),
+            ("someone.github.io", "someone.github.io"),
+            ("Example.COM:8080", "example.com"),
+            ("example.unknowntld", "example.unknowntld"),
+            ("a.b.example.ck", "b.example.ck"),
+            ("www.ck", "www.ck"),
+            ("co.uk", "co.uk"),
+            ("127.0.0.1", "127.0.0.1"),
+            ("www.bangkokpost.co.th", "bangkokpost.co.th"),
+            ("vnexpress.com.vn", "vnexpress.com.vn"),
+            ("news.example.com.sa", "example.com.sa"),
+        ];
+        for (host, expected) in domains.iter() {
+            assert_eq!(registrable_domain(host), *expected, "{}", host);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Filters selecting the posts to keep in a dataset
+
+use crate::reddit_comment::RedditComment;
+use crate::reddit_post::RedditPost;
+use regex::RegexSet;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+/// Filters selecting the posts to keep.
+/// A post is kept if it passes every filter.
+#[derive(Debug, Clone, Default)]
+pub struct PostFilter {
+    /// Keep the posts created at or after this timestamp
+    pub min_created_utc: Option<i64>,
+    /// Keep the posts created strictly before this timestamp
+    pub max_created_utc: Option<i64>,
+    /// Keep only the posts of these subreddits, in lowercase
+    pub included_subreddits: Option<HashSet<String>>,
+    /// Remove the posts of these subreddits, in lowercase
+    pub excluded_subreddits: HashSet<String>,
+    /// Keep the posts with at least this score
+    pub min_score: Option<i32>,
+    /// Keep the posts whose title matches one of these regexes
+    pub title_regexes: Option<RegexSet>,
+    /// Keep the posts whose url matches one of these regexes
+    pub url_regexes: Option<RegexSet>,
+    /// Remove the posts whose url matches one of these regexes
+    pub excluded_url_regexes: Option<RegexSet>,
+}
+
+impl PostFilter {
+    /// Check if a post should be kept
+    pub fn keep_post(&self, post: &RedditPost) -> bool {
+        if self.title_regexes.as_ref().is_some_and(|r| !r.is_match(&post.title))
+            || self.url_regexes.as_ref().is_some_and(|r| !r.is_match(&post.url))
+            || self.excluded_url_regexes.as_ref().is_some_and(|r| r.is_match(&post.url))
+        {
+            return false;
+        }
+        self.keep(&post.subreddit, post.created_utc, post.score)
+    }
+
+    /// Check if a comment should be kept.
+    /// The title and url filters are not used, since comments don't have titles or urls.
+    pub fn keep_comment(&self, comment: &RedditComment) -> bool {
+        self.keep(&comment.subreddit, comment.created_utc, comment.score)
+    }
+
+    fn keep(&self, subreddit: &str, created_utc: i64, score: i32) -> bool {
+        if self.min_created_utc.is_some_and(|min| created_utc < min)
+            || self.max_created_utc.is_some_and(|max| created_utc >= max)
+            || self.min_score.is_some_and(|min| score < min)
+        {
+            return false;
+        }
+        if self.included_subreddits.is_none() && self.excluded_subreddits.is_empty() {
+            return true;
+        }
+        let subreddit = subreddit.to_lowercase();
+        if let Some(included_subreddits) = &self.included_subreddits {
+            if !included_subreddits.contains(&subreddit) {
+                return false;
+            }
+        }
+        !self.excluded_subreddits.contains(&subreddit)
+    }
+}
+
+/// Iterate over the posts kept by the filter
+pub fn filter_posts<'a, IT>(iterator: IT, filter: &'a PostFilter) -> impl Iterator<Item = RedditPost> + 'a
+where
+    IT: Iterator<Item = RedditPost> + 'a,
+{
+    iterator.filter(move |post| filter.keep_post(post))
+}
+
+/// Load a list of subreddits from a file, with one subreddit per line.
+/// Empty lines and lines starting with `#` are ignored, and the names are converted to lowercase.
+pub fn load_subreddit_list(filepath: &str) -> HashSet<String> {
+    let file = File::open(filepath).unwrap_or_else(|e| panic!("Cannot open {}: {}", filepath, e));
+    BufReader::new(file)
+        .lines()
+        .map(Result::unwrap)
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Parse a timestamp given as a number of seconds since the epoch,
+/// or as a `YYYY-MM-DD` date, corresponding to midnight UTC.
+pub fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    if let Ok(timestamp) = timestamp.parse() {
+        return Some(timestamp);
+    }
+    let mut parts = timestamp.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86400)
+}
+
+/// Number of days since the epoch of a date of the proleptic gregorian calendar.
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reddit_post::test_post;
+
+    #[test]
+    fn parse_dates() {
+        assert_eq!(parse_timestamp("1293952912"), Some(1293952912));
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2011-01-02"), Some(1293926400));
+        assert_eq!(parse_timestamp("2020-03-01"), Some(1583020800));
+        assert_eq!(parse_timestamp("2020-13-01"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn filter_posts() {
+        let mut post = RedditPost {
+            score: 5,
+            ..test_post("a", "AskReddit", "u", 100)
+        };
+        let mut filter = PostFilter {
+            min_created_utc: Some(100),
+            max_created_utc: Some(200),
+            included_subreddits: Some(["askreddit".to_string()].iter().cloned().collect()),
+            ..PostFilter::default()
+        };
+        assert!(filter.keep_post(&post));
+        filter.min_score = Some(6);
+        assert!(!filter.keep_post(&post));
+        filter.min_score = None;
+        filter.excluded_subreddits.insert("askreddit".to_string());
+        assert!(!filter.keep_post(&post));
+        filter.excluded_subreddits.clear();
+        post.created_utc = 200;
+        assert!(!filter.keep_post(&post));
+    }
+
+    #[test]
+    fn filter_posts_with_regexes() {
+        let post = RedditPost {
+            title: "Deftones - Change".to_string(),
+            ..test_post("a", "music", "https://www.youtube.com/watch?v=ZL4MGwlZuAc", 100)
+        };
+        let regexes = |regexes: &[&str]| Some(RegexSet::new(regexes).unwrap());
+        let mut filter = PostFilter {
+            title_regexes: regexes(&["(?i)metallica", "(?i)deftones"]),
+            url_regexes: regexes(&[r"youtube\.com/", r"youtu\.be/"]),
+            ..PostFilter::default()
+        };
+        assert!(filter.keep_post(&post));
+        filter.excluded_url_regexes = regexes(&["watch"]);
+        assert!(!filter.keep_post(&post));
+        filter.excluded_url_regexes = None;
+        filter.title_regexes = regexes(&["^Metallica"]);
+        assert!(!filter.keep_post(&post));
+    }
+}
@@ -1,26 +1,54 @@
+mod codegen;
 mod data_analysis;
+mod dataset_format;
+mod dedup;
+mod deserializers;
+mod domains;
+mod filters;
+mod media;
 mod possible_types;
 mod read_files;
+mod reddit_comment;
 mod reddit_post;
+mod shorteners;
 mod simplify_dataset;
 mod subreddit_posts;
 mod subreddit_stats;
+mod title_similarity;
+mod url_canonicalization;
+mod url_keys;
+mod url_rules;
 mod utils;
+mod validate_schema;
 
+use crate::codegen::*;
 use crate::data_analysis::*;
+use crate::dataset_format::*;
+use crate::dedup::*;
+use crate::filters::*;
+use crate::media::*;
+use crate::possible_types::*;
 use crate::read_files::*;
+use crate::reddit_comment::*;
 use crate::reddit_post::*;
+use crate::shorteners::*;
 use subreddit_stats::*;
 use simplify_dataset::*;
-use clap::{Arg, App, SubCommand};
+use title_similarity::*;
+use url_canonicalization::*;
+use url_keys::*;
+use url_rules::*;
+use validate_schema::*;
+use clap::{Arg, App, ArgGroup, ArgMatches, SubCommand};
+use regex::RegexSet;
 
 /// Compute and write to a file the PPMI matrix
-fn write_ppmi_matrix<I: Iterator<Item = RedditPost>>(post_iterator: I, stats_filepath: &str, output_filepath: &str, n_subreddits: usize) {
+fn write_ppmi_matrix<I: Iterator<Item = RedditPost>>(post_iterator: I, stats_filepath: &str, output_filepath: &str, n_subreddits: usize, keyer: &UrlKeyer) {
     let stats = load_subreddits_stats(stats_filepath);
     let stats = get_most_popular_subreddits(n_subreddits, stats);
-    let best_subreddits: HashSet<_> = stats.clone().into_iter().map(|(s, _)| s).collect();
+    let best_subreddits: HashSet<_> = stats.clone().into_keys().collect();
     println!("Got subreddits");
-    let urls = get_urls(post_iterator, Some(&best_subreddits));
+    let urls = get_urls(post_iterator, Some(&best_subreddits), keyer, false);
     println!("Got urls: {} urls considered", urls.urls.len());
     let urls_between_subreddits = get_shared_urls_between_subreddits(urls);
     let ppmi = compute_ppmi(urls_between_subreddits);
@@ -42,52 +70,365 @@ fn get_subreddit_stats(stats_filepath: &str, subreddits: Vec<&str>) {
 }
 
 /// Get the reposts that comes from and to a particular subreddit.
-fn get_reposts(subreddit: &str, inputs_filepath: Vec<&str>) {
-    let it = CSVItemIterator::<RedditPost,_>::new(inputs_filepath.clone().into_iter().map(|s| s.to_string()));
+/// If comment files are given, also compare the number of comments of original posts and reposts.
+fn get_reposts(subreddit: &str, inputs_filepath: Vec<&str>, comments_filepath: Option<Vec<&str>>, filter: &PostFilter, keyer: &UrlKeyer, dedup: bool) {
+    // The posts are read several times, but the standard input can only be read once
+    let stdin_posts: Option<Vec<_>> = if inputs_filepath.contains(&STDIO_PATH) {
+        Some(read_posts(inputs_filepath.clone(), filter, dedup).collect())
+    } else {
+        None
+    };
+    // The freshest records are found once for all the passes over the files
+    let freshest = if dedup && stdin_posts.is_none() {
+        Some(FreshestPosts::find(read_posts(inputs_filepath.clone(), filter, false)))
+    } else {
+        None
+    };
+    // Read the posts for one of the passes
+    let read_pass = || -> Box<dyn Iterator<Item = RedditPost>> {
+        match (&stdin_posts, &freshest) {
+            (Some(posts), _) => Box::new(posts.iter().cloned()),
+            (None, Some(freshest)) => Box::new(freshest.keep(read_posts(inputs_filepath.clone(), filter, false))),
+            (None, None) => read_posts(inputs_filepath.clone(), filter, false),
+        }
+    };
+    let it = read_pass();
     let mut subreddit_singleton = HashSet::new();
     subreddit_singleton.insert(subreddit.to_string());
     println!("Fetching urls...");
-    let mut urls = get_urls(it, Some(&subreddit_singleton));
+    let mut urls = get_urls(it, Some(&subreddit_singleton), keyer, true);
     println!("Subreddit urls fetched!");
-    let it = CSVItemIterator::<RedditPost,_>::new(inputs_filepath.clone().into_iter().map(|s| s.to_string()));
+    let it = read_pass();
     println!("Fetching other surbeddits...");
-    get_posts_with_urls(it, &mut urls);
+    get_posts_with_urls(it, &mut urls, keyer, true);
     println!("Other subreddits found");
-    let reposts_stats = get_reposts_stats(subreddit, &urls).sort(10).display(urls.subreddits);
+    if !urls.crossposts.is_empty() {
+        let it = read_pass();
+        println!("Fetching crossposts parents...");
+        link_crosspost_parents(it, &mut urls);
+        println!("Crossposts parents found");
+    }
+    let reposts_stats = get_reposts_stats(subreddit, &urls).sort(10).display(urls.subreddits.clone());
     println!("{:#?}", reposts_stats);
+    if let Some(comments_filepath) = comments_filepath {
+        let it = read_pass();
+        let posts_roles = get_posts_roles(it, &urls, keyer);
+        println!("Fetching comments...");
+        let it = DatasetItemIterator::<RedditComment,_>::new(comments_filepath.into_iter().map(|s| s.to_string()));
+        let n_comments = count_comments_per_post(it, &posts_roles);
+        println!("Comments fetched!");
+        println!("{:#?}", get_discussion_stats(&posts_roles, &n_comments));
+    }
+}
+
+/// Arguments of the filters selecting the posts
+fn post_filter_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("AFTER")
+            .help("Keep the posts created at or after this date (YYYY-MM-DD or UTC timestamp)")
+            .long("after")
+            .takes_value(true),
+        Arg::with_name("BEFORE")
+            .help("Keep the posts created before this date (YYYY-MM-DD or UTC timestamp)")
+            .long("before")
+            .takes_value(true),
+        Arg::with_name("SUBREDDITS")
+            .help("Keep only the posts of these comma separated subreddits")
+            .long("subreddits")
+            .takes_value(true)
+            .use_delimiter(true),
+        Arg::with_name("SUBREDDITS_FILE")
+            .help("Keep only the posts of the subreddits listed in this file, one per line")
+            .long("subreddits-file")
+            .takes_value(true),
+        Arg::with_name("EXCLUDE_SUBREDDITS")
+            .help("Remove the posts of these comma separated subreddits")
+            .long("exclude-subreddits")
+            .takes_value(true)
+            .use_delimiter(true),
+        Arg::with_name("EXCLUDE_SUBREDDITS_FILE")
+            .help("Remove the posts of the subreddits listed in this file, one per line")
+            .long("exclude-subreddits-file")
+            .takes_value(true),
+        Arg::with_name("MIN_SCORE")
+            .help("Keep the posts with at least this score")
+            .long("min-score")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+        Arg::with_name("TITLE_REGEX")
+            .help("Keep the posts whose title matches this regex, or one of the regexes if repeated")
+            .long("title-regex")
+            .takes_value(true)
+            .number_of_values(1)
+            .multiple(true),
+        Arg::with_name("URL_REGEX")
+            .help("Keep the posts whose url matches this regex, or one of the regexes if repeated")
+            .long("url-regex")
+            .takes_value(true)
+            .number_of_values(1)
+            .multiple(true),
+        Arg::with_name("EXCLUDE_URL_REGEX")
+            .help("Remove the posts whose url matches this regex")
+            .long("exclude-url-regex")
+            .takes_value(true)
+            .number_of_values(1)
+            .multiple(true),
+    ]
+}
+
+/// Argument removing the posts read several times
+fn dedup_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("DEDUP")
+        .help("Keep only the freshest record of the posts present several times in the input files, like overlapping dumps")
+        .long("dedup")
+}
+
+/// Read the posts of simplified datasets kept by the filter, without duplicates if `dedup` is true.
+/// The posts are filtered before being deduplicated.
+fn read_posts<'a>(filepaths: Vec<&str>, filter: &'a PostFilter, dedup: bool) -> Box<dyn Iterator<Item = RedditPost> + 'a> {
+    let filepaths: Vec<_> = filepaths.into_iter().map(|s| s.to_string()).collect();
+    if dedup {
+        read_freshest_posts(filepaths, filter).1
+    } else {
+        Box::new(filter_posts(DatasetItemIterator::new(filepaths.into_iter()), filter))
+    }
 }
 
+/// Arguments of the keys grouping the posts linking to the same content
+fn url_keyer_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("SELFTEXT_URLS")
+            .help("Also use the links found in the text of self posts. The datasets should be simplified with the rich columns")
+            .long("selftext-urls"),
+        Arg::with_name("SHORT_URLS")
+            .help("Replace the short urls by their expanded url, given in this file with one short url and its expanded url per line, separated by a tab")
+            .long("short-urls")
+            .takes_value(true),
+        Arg::with_name("SKIP_CANONICALIZATION")
+            .help("Disable these comma separated url canonicalization rules, or all of them")
+            .long("skip-canonicalization")
+            .takes_value(true)
+            .use_delimiter(true)
+            .possible_values(&CANONICALIZATION_RULES)
+            .possible_value("all"),
+        Arg::with_name("URL_RULES")
+            .help("Rewrite the canonical urls with the rules of this JSON file, applied in order")
+            .long("url-rules")
+            .takes_value(true),
+        Arg::with_name("GRANULARITY")
+            .help("Group the posts by url, by the media of the known hosts (youtube, imgur, gfycat...), or by registrable domain")
+            .long("granularity")
+            .takes_value(true)
+            .possible_values(&["url", "media", "domain"])
+            .default_value("url"),
+        Arg::with_name("MEDIA")
+            .help("Same as --granularity media")
+            .long("media"),
+        Arg::with_name("TITLE_CLUSTERS")
+            .help("Group the posts by the clusters of similar titles of this file, written by the title_clusters command, instead of their urls")
+            .long("title-clusters")
+            .takes_value(true),
+    ]
+}
+
+/// Check if any of the arguments of `url_keyer_args` is explicitly given
+fn is_url_keyer_given(matches: &ArgMatches) -> bool {
+    matches.occurrences_of("GRANULARITY") > 0
+        || ["MEDIA", "SELFTEXT_URLS", "SHORT_URLS", "SKIP_CANONICALIZATION", "URL_RULES", "TITLE_CLUSTERS"]
+            .iter()
+            .any(|arg| matches.is_present(arg))
+}
+
+/// Get the keyer from the arguments given by `url_keyer_args`
+fn get_url_keyer(matches: &ArgMatches) -> UrlKeyer {
+    let mut canonicalizer = UrlCanonicalizer::all();
+    for rule in matches.values_of("SKIP_CANONICALIZATION").into_iter().flatten() {
+        if rule == "all" {
+            canonicalizer = UrlCanonicalizer::default();
+        } else {
+            canonicalizer.set_rule(rule, false).unwrap();
+        }
+    }
+    let granularity = match matches.value_of("GRANULARITY").unwrap() {
+        _ if matches.is_present("MEDIA") => Granularity::Media,
+        "media" => Granularity::Media,
+        "domain" => Granularity::Domain,
+        _ => Granularity::Url,
+    };
+    UrlKeyer {
+        selftext_urls: matches.is_present("SELFTEXT_URLS"),
+        short_urls: matches.value_of("SHORT_URLS").map(load_short_url_table).unwrap_or_default(),
+        canonicalizer,
+        url_rules: matches.value_of("URL_RULES").map(load_url_rules).unwrap_or_default(),
+        granularity,
+        media_extractors: default_extractors(),
+        title_clusters: matches.value_of("TITLE_CLUSTERS").map(load_title_clusters),
+    }
+}
+
+/// Print the urls linked by the posts, and their keys
+fn print_url_keys<I: Iterator<Item = RedditPost>>(post_iterator: I, keyer: &UrlKeyer, changed_only: bool) {
+    for post in post_iterator {
+        for url in post.get_linked_urls(keyer.selftext_urls) {
+            let key = keyer.url_key(&url);
+            if !changed_only || key != url {
+                println!("{}\t{}", url, key);
+            }
+        }
+    }
+}
+
+/// Get the regexes given by a repeatable argument
+fn get_regexes(matches: &ArgMatches, arg: &str) -> Option<RegexSet> {
+    matches.values_of(arg).map(|regexes| RegexSet::new(regexes).unwrap_or_else(|e| panic!("Error: invalid regex in {} argument: {}", arg, e)))
+}
+
+/// Get the subreddits given inline or in a file
+fn get_subreddit_list(matches: &ArgMatches, inline_arg: &str, file_arg: &str) -> Option<HashSet<String>> {
+    let mut subreddits: Option<HashSet<_>> = matches.values_of(inline_arg).map(|values| values.map(str::to_lowercase).collect());
+    if let Some(filepath) = matches.value_of(file_arg) {
+        subreddits.get_or_insert_with(HashSet::new).extend(load_subreddit_list(filepath));
+    }
+    subreddits
+}
+
+/// Get the filter selecting the posts from the arguments given by `post_filter_args`
+fn get_post_filter(matches: &ArgMatches) -> PostFilter {
+    let timestamp = |arg| matches.value_of(arg).map(|t| parse_timestamp(t).unwrap_or_else(|| panic!("Error: date or timestamp expected in {} argument", arg)));
+    PostFilter {
+        min_created_utc: timestamp("AFTER"),
+        max_created_utc: timestamp("BEFORE"),
+        included_subreddits: get_subreddit_list(matches, "SUBREDDITS", "SUBREDDITS_FILE"),
+        excluded_subreddits: get_subreddit_list(matches, "EXCLUDE_SUBREDDITS", "EXCLUDE_SUBREDDITS_FILE").unwrap_or_default(),
+        min_score: matches.value_of("MIN_SCORE").map(|n| n.parse().expect("Error: integer parameter expected in MIN_SCORE argument")),
+        title_regexes: get_regexes(matches, "TITLE_REGEX"),
+        url_regexes: get_regexes(matches, "URL_REGEX"),
+        excluded_url_regexes: get_regexes(matches, "EXCLUDE_URL_REGEX"),
+    }
+}
 
 fn main() {
     let matches = App::new("Reddit Repost")
         .subcommand(SubCommand::with_name("simplify")
                     .about("simplify a dataset")
                     .arg(Arg::with_name("INPUT")
-                         .help("Set the input file path to simplify")
+                         .help("Set the input file path to simplify, or - to read the standard input")
                          .required(true)
                          .index(1))
+                    .arg(Arg::with_name("KIND")
+                         .help("Set the kind of dataset, guessed from the file name (RS_ or RC_) if not given")
+                         .long("kind")
+                         .takes_value(true)
+                         .possible_values(&["submissions", "comments"]))
+                    .arg(Arg::with_name("ON_ERROR")
+                         .help("Set what to do with the lines that cannot be parsed")
+                         .long("on-error")
+                         .takes_value(true)
+                         .possible_values(&["fail", "skip", "quarantine"])
+                         .default_value("fail"))
+                    .arg(Arg::with_name("QUARANTINE_FILE")
+                         .help("Set the file where the rejected lines are written, defaults to the output file path followed by _rejected")
+                         .long("quarantine-file")
+                         .takes_value(true))
+                    .arg(Arg::with_name("THREADS")
+                         .help("Set the number of threads parsing the JSON objects")
+                         .short("j")
+                         .long("threads")
+                         .takes_value(true)
+                         .default_value("1"))
+                    .args(&post_filter_args())
+                    .arg(Arg::with_name("COLUMNS")
+                         .help("Set the columns written for the posts. The rich set adds the domain, is_self, selftext, crosspost_parent, link_flair_text and num_crossposts columns")
+                         .long("columns")
+                         .takes_value(true)
+                         .possible_values(&["lean", "rich"])
+                         .default_value("lean"))
+                    .arg(Arg::with_name("FORMAT")
+                         .help("Set the format of the output file. The format of the input datasets is detected by the commands reading them")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["csv", "ndjson", "binary"])
+                         .default_value("csv"))
                     .arg(Arg::with_name("KEEP_NON_URL_POSTS")
                          .help("Keep the post which don't contain url")
                          .default_value("true")
                          .index(2))
                     .arg(Arg::with_name("OUTPUT")
-                         .help("Set the output file path")
+                         .help("Set the output file path, or - to write to the standard output")
                          .short("o")
-                         .long("output")))
+                         .long("output")
+                         .takes_value(true)))
+        .subcommand(SubCommand::with_name("infer_schema")
+                    .about("Print the fields of the JSON objects of datasets, with the types they take")
+                    .arg(Arg::with_name("INPUTS")
+                         .help("Set the input file paths to analyse")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(1)
+                         .index(1))
+                    .arg(Arg::with_name("MAX_LINES")
+                         .help("Only read the first lines of each input file")
+                         .long("max-lines")
+                         .takes_value(true))
+                    .arg(Arg::with_name("RUST_STRUCT")
+                         .help("Print a Rust struct with the given name deserializing the objects, instead of the tree of fields")
+                         .long("rust-struct")
+                         .takes_value(true))
+                    .arg(Arg::with_name("SAVE")
+                         .help("Save the inferred schema in a JSON file, to be used by the validate command")
+                         .long("save")
+                         .takes_value(true)))
+        .subcommand(SubCommand::with_name("validate")
+                    .about("Check that the JSON objects of datasets match a schema saved by the infer_schema command")
+                    .arg(Arg::with_name("SCHEMA")
+                         .help("The schema file saved by the infer_schema command")
+                         .required(true)
+                         .index(1))
+                    .arg(Arg::with_name("INPUTS")
+                         .help("Set the input file paths to validate")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(1)
+                         .index(2))
+                    .arg(Arg::with_name("MAX_LINES")
+                         .help("Only read the first lines of each input file")
+                         .long("max-lines")
+                         .takes_value(true))
+                    .arg(Arg::with_name("MAX_EXAMPLES")
+                         .help("The number of record ids shown for each issue")
+                         .long("max-examples")
+                         .takes_value(true)
+                         .default_value("5")))
         .subcommand(SubCommand::with_name("compute_stats")
                     .about("Computes general statistics of subreddits")
+                    .args(&post_filter_args())
+                    .args(&url_keyer_args())
+                    .arg(dedup_arg())
                     .arg(Arg::with_name("OUTPUT")
-                         .help("Set the output file path")
+                         .help("Set the output file path, or - to write to the standard output")
                          .required(true)
                          .index(1))
                     .arg(Arg::with_name("INPUTS")
-                         .help("Set the input file paths to analyse")
+                         .help("Set the input file paths to analyse, or - to read the standard input")
                          .required(true)
                          .multiple(true)
                          .min_values(1)
                          .index(2)))
+        .subcommand(SubCommand::with_name("migrate_stats")
+                    .about("Convert a stats file computed by a previous version into the current format")
+                    .arg(Arg::with_name("INPUT")
+                         .help("The stats file to convert")
+                         .required(true)
+                         .index(1))
+                    .arg(Arg::with_name("OUTPUT")
+                         .help("Set the output file path")
+                         .required(true)
+                         .index(2)))
         .subcommand(SubCommand::with_name("ppmi")
                     .about("Computes the ppmi matrix found by comparing the shared urls between subreddits")
+                    .args(&post_filter_args())
+                    .args(&url_keyer_args())
+                    .arg(dedup_arg())
                     .arg(Arg::with_name("OUTPUT")
                          .help("The output file that should be read by tsne.py")
                          .required(true)
@@ -101,7 +442,7 @@ fn main() {
                          .required(true)
                          .index(3))
                     .arg(Arg::with_name("INPUTS")
-                         .help("The input failes containing the posts in CSV format")
+                         .help("The input failes containing the posts in CSV format, or - to read the standard input")
                          .required(true)
                          .multiple(true)
                          .min_values(1)
@@ -119,47 +460,238 @@ fn main() {
                          .multiple(true)))
         .subcommand(SubCommand::with_name("get_reposts")
                     .about("Get the number of post reposted by the sub, and by other subs over a url sent first on that sub")
+                    .args(&post_filter_args())
+                    .args(&url_keyer_args())
+                    .arg(dedup_arg())
                     .arg(Arg::with_name("SUBREDDIT")
                          .help("The name of the subreddit to analyse")
                          .required(true)
                          .index(1))
                     .arg(Arg::with_name("INPUTS")
-                         .help("The dataset files created by the simplify command")
+                         .help("The dataset files created by the simplify command, or - to read the standard input")
                          .required(true)
                          .index(2)
                          .min_values(1)
+                         .multiple(true))
+                    .arg(Arg::with_name("COMMENTS")
+                         .help("The comment files created by the simplify command, to compare the discussion on original posts and reposts")
+                         .long("comments")
+                         .takes_value(true)
+                         .min_values(1)
                          .multiple(true)))
+        .subcommand(SubCommand::with_name("canonical_urls")
+                    .about("Print the urls of the posts and their keys, separated by a tab")
+                    .args(&post_filter_args())
+                    .args(&url_keyer_args())
+                    .arg(Arg::with_name("CHANGED_ONLY")
+                         .help("Print only the urls whose key is different")
+                         .long("changed-only"))
+                    .arg(Arg::with_name("INPUTS")
+                         .help("The dataset files created by the simplify command")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(1)
+                         .index(1)))
+        .subcommand(SubCommand::with_name("test_url_rules")
+                    .about("Print the steps of the computation of the keys of some urls, with the url rules that matched")
+                    .args(&url_keyer_args())
+                    .group(ArgGroup::with_name("RULES")
+                           .arg("URL_RULES")
+                           .required(true))
+                    .arg(Arg::with_name("URLS")
+                         .help("The urls to test")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(1)
+                         .index(1)))
+        .subcommand(SubCommand::with_name("shortener_report")
+                    .about("Print the most common short urls that are not resolved, with their number of occurrences")
+                    .args(&post_filter_args())
+                    .args(&url_keyer_args())
+                    .arg(Arg::with_name("TOP")
+                         .help("The number of short urls to print")
+                         .long("top")
+                         .takes_value(true)
+                         .default_value("100"))
+                    .arg(Arg::with_name("INPUTS")
+                         .help("The dataset files created by the simplify command")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(1)
+                         .index(1)))
+        .subcommand(SubCommand::with_name("title_clusters")
+                    .about("Find the clusters of posts with near-identical titles, and write them in a file with one JSON object per line")
+                    .args(&post_filter_args())
+                    .arg(Arg::with_name("THRESHOLD")
+                         .help("The minimal similarity of the titles of a cluster, between 0 and 1")
+                         .long("threshold")
+                         .takes_value(true)
+                         .default_value("0.8"))
+                    .arg(Arg::with_name("BANDS")
+                         .help("The number of bands of the locality-sensitive hashing")
+                         .long("bands")
+                         .takes_value(true)
+                         .default_value("16"))
+                    .arg(Arg::with_name("ROWS")
+                         .help("The number of hashes in each band of the locality-sensitive hashing")
+                         .long("rows")
+                         .takes_value(true)
+                         .default_value("4"))
+                    .arg(Arg::with_name("MIN_TITLE_LENGTH")
+                         .help("Ignore the titles shorter than this number of characters")
+                         .long("min-title-length")
+                         .takes_value(true)
+                         .default_value("20"))
+                    .arg(Arg::with_name("OUTPUT")
+                         .help("Set the output file path")
+                         .required(true)
+                         .index(1))
+                    .arg(Arg::with_name("INPUTS")
+                         .help("The dataset files created by the simplify command")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(1)
+                         .index(2)))
+        .subcommand(SubCommand::with_name("dedup")
+                    .about("Write the posts of simplified datasets in a new dataset, keeping only the freshest record of the posts present several times")
+                    .arg(Arg::with_name("COLUMNS")
+                         .help("The columns written in the output file")
+                         .long("columns")
+                         .takes_value(true)
+                         .possible_values(&["lean", "rich"])
+                         .default_value("lean"))
+                    .arg(Arg::with_name("FORMAT")
+                         .help("Set the format of the output file. The format of the input datasets is detected by the commands reading them")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["csv", "ndjson", "binary"])
+                         .default_value("csv"))
+                    .arg(Arg::with_name("OUTPUT")
+                         .help("Set the output file path, or - to write to the standard output")
+                         .required(true)
+                         .index(1))
+                    .arg(Arg::with_name("INPUTS")
+                         .help("The dataset files created by the simplify command")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(1)
+                         .index(2)))
         .get_matches();
 
     if let Some(matches) = matches.subcommand_matches("simplify") {
         let filepath = matches.value_of("INPUT").unwrap();
         let keep_non_url_posts: bool = matches.value_of("KEEP_NON_URL_POSTS").unwrap().parse().expect("Error: bool parameter expected in  KEEP_NON_URL_POSTS argument");
-        let output_filepath_default = if keep_non_url_posts {
-            filepath.to_string() + "_CSV"
+        let format = DatasetFormat::from_name(matches.value_of("FORMAT").unwrap()).unwrap();
+        let format_suffix = match format {
+            DatasetFormat::Csv => "_CSV",
+            DatasetFormat::Ndjson => "_NDJSON",
+            DatasetFormat::Binary => "_BIN",
+        };
+        let output_filepath_default = if filepath == STDIO_PATH {
+            STDIO_PATH.to_string()
+        } else if keep_non_url_posts {
+            filepath.to_string() + format_suffix
         } else {
-            filepath.to_string() + "_CSV_url"
+            filepath.to_string() + format_suffix + "_url"
         };
         let output_filepath = matches.value_of("OUTPUT").unwrap_or(&output_filepath_default);
-        simplify_post_dataset(filepath, &output_filepath, keep_non_url_posts);
+        let kind = match matches.value_of("KIND") {
+            Some("comments") => DatasetKind::Comments,
+            Some(_) => DatasetKind::Submissions,
+            None => DatasetKind::from_filepath(filepath),
+        };
+        let error_policy = match matches.value_of("ON_ERROR").unwrap() {
+            "skip" => ErrorPolicy::Skip,
+            "quarantine" => {
+                if output_filepath == STDIO_PATH && !matches.is_present("QUARANTINE_FILE") {
+                    panic!("Error: QUARANTINE_FILE argument expected when writing to the standard output");
+                }
+                let quarantine_filepath_default = output_filepath.to_string() + "_rejected";
+                let quarantine_filepath = matches.value_of("QUARANTINE_FILE").unwrap_or(&quarantine_filepath_default);
+                ErrorPolicy::Quarantine(quarantine_filepath.to_string())
+            }
+            _ => ErrorPolicy::Fail,
+        };
+        let n_threads: usize = matches.value_of("THREADS").unwrap().parse().expect("Error: integer parameter expected in THREADS argument");
+        let options = SimplifyOptions {
+            error_policy,
+            n_threads,
+            format,
+        };
+        let filter = get_post_filter(matches);
+        let columns = match matches.value_of("COLUMNS").unwrap() {
+            "rich" => ColumnSet::Rich,
+            _ => ColumnSet::Lean,
+        };
+        let summary = match kind {
+            DatasetKind::Submissions => simplify_post_dataset(filepath, output_filepath, keep_non_url_posts, &filter, columns, &options),
+            DatasetKind::Comments => simplify_comment_dataset(filepath, output_filepath, &filter, &options),
+        };
+        if output_filepath == STDIO_PATH {
+            eprintln!("{:#?}", summary);
+        } else {
+            println!("{:#?}", summary);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("infer_schema") {
+        let filepaths: Vec<_> = matches.values_of("INPUTS").unwrap().collect();
+        let max_lines = matches.value_of("MAX_LINES").map(|n| n.parse().expect("Error: integer parameter expected in MAX_LINES argument"));
+        let (possible_types, n_invalid_lines) = get_all_possible_types(&filepaths, max_lines);
+        if let Some(schema_filepath) = matches.value_of("SAVE") {
+            save_possible_types(&possible_types, schema_filepath);
+        }
+        if let Some(struct_name) = matches.value_of("RUST_STRUCT") {
+            print!("{}", generate_rust_struct(&possible_types, struct_name));
+        } else {
+            print!("{}", possible_types);
+            println!("{} invalid lines", n_invalid_lines);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("validate") {
+        let schema = load_possible_types(matches.value_of("SCHEMA").unwrap());
+        let filepaths: Vec<_> = matches.values_of("INPUTS").unwrap().collect();
+        let max_lines = matches.value_of("MAX_LINES").map(|n| n.parse().expect("Error: integer parameter expected in MAX_LINES argument"));
+        let max_examples = matches.value_of("MAX_EXAMPLES").unwrap().parse().expect("Error: integer parameter expected in MAX_EXAMPLES argument");
+        let report = validate_files(&schema, &filepaths, max_lines, max_examples);
+        print!("{}", report);
+        if !report.is_valid() {
+            std::process::exit(1);
+        }
         return;
     }
 
     if let Some(matches) = matches.subcommand_matches("compute_stats") {
         let filepaths: Vec<_> = matches.values_of("INPUTS").unwrap().collect();
         let output_filepath = matches.value_of("OUTPUT").unwrap();
-        let it = CSVItemIterator::<RedditPost,_>::new(filepaths.into_iter().map(|s| s.to_string()));
-        let stats = compute_subreddits_stats(it);
+        let filter = get_post_filter(matches);
+        let it = read_posts(filepaths, &filter, matches.is_present("DEDUP"));
+        // Counting the distinct keys keeps the keys of every subreddit in memory
+        let keyer = Some(get_url_keyer(matches)).filter(|_| is_url_keyer_given(matches));
+        let stats = compute_subreddits_stats(it, keyer.as_ref());
         save_subreddits_stats(&stats, output_filepath);
         return;
     }
 
+    if let Some(matches) = matches.subcommand_matches("migrate_stats") {
+        let input_filepath = matches.value_of("INPUT").unwrap();
+        let output_filepath = matches.value_of("OUTPUT").unwrap();
+        migrate_subreddits_stats(input_filepath, output_filepath);
+        return;
+    }
+
     if let Some(matches) = matches.subcommand_matches("ppmi") {
         let output_filepath = matches.value_of("OUTPUT").unwrap();
         let stats_filepath = matches.value_of("STATS_FILE").unwrap();
         let inputs_filepath: Vec<_> = matches.values_of("INPUTS").unwrap().collect();
         let n_subreddits: usize = matches.value_of("N_SUBREDDITS").unwrap().parse().unwrap();
-        let it = CSVItemIterator::<RedditPost,_>::new(inputs_filepath.into_iter().map(|s| s.to_string()));
-        write_ppmi_matrix(it, stats_filepath, output_filepath, n_subreddits);
+        let filter = get_post_filter(matches);
+        let it = read_posts(inputs_filepath, &filter, matches.is_present("DEDUP"));
+        let keyer = get_url_keyer(matches);
+        write_ppmi_matrix(it, stats_filepath, output_filepath, n_subreddits, &keyer);
         return;
     }
 
@@ -173,6 +705,78 @@ fn main() {
     if let Some(matches) = matches.subcommand_matches("get_reposts") {
         let subreddit = matches.value_of("SUBREDDIT").unwrap();
         let inputs_filepath = matches.values_of("INPUTS").unwrap().collect();
-        get_reposts(subreddit, inputs_filepath);
+        let comments_filepath = matches.values_of("COMMENTS").map(|values| values.collect());
+        let filter = get_post_filter(matches);
+        let keyer = get_url_keyer(matches);
+        get_reposts(subreddit, inputs_filepath, comments_filepath, &filter, &keyer, matches.is_present("DEDUP"));
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("canonical_urls") {
+        let inputs_filepath: Vec<_> = matches.values_of("INPUTS").unwrap().collect();
+        let it = DatasetItemIterator::<RedditPost,_>::new(inputs_filepath.into_iter().map(|s| s.to_string()));
+        let filter = get_post_filter(matches);
+        let it = filter_posts(it, &filter);
+        let keyer = get_url_keyer(matches);
+        print_url_keys(it, &keyer, matches.is_present("CHANGED_ONLY"));
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("test_url_rules") {
+        let keyer = get_url_keyer(matches);
+        for url in matches.values_of("URLS").unwrap() {
+            print!("{}", keyer.trace_url_key(url));
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("shortener_report") {
+        let inputs_filepath: Vec<_> = matches.values_of("INPUTS").unwrap().collect();
+        let n_urls: usize = matches.value_of("TOP").unwrap().parse().expect("Error: integer parameter expected in TOP argument");
+        let it = DatasetItemIterator::<RedditPost,_>::new(inputs_filepath.into_iter().map(|s| s.to_string()));
+        let filter = get_post_filter(matches);
+        let it = filter_posts(it, &filter);
+        let keyer = get_url_keyer(matches);
+        for (url, n_posts) in get_unresolved_short_urls(it, &keyer, n_urls) {
+            println!("{}\t{}", url, n_posts);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("title_clusters") {
+        let output_filepath = matches.value_of("OUTPUT").unwrap();
+        let inputs_filepath: Vec<_> = matches.values_of("INPUTS").unwrap().collect();
+        let options = TitleSimilarityOptions {
+            threshold: matches.value_of("THRESHOLD").unwrap().parse().expect("Error: float parameter expected in THRESHOLD argument"),
+            n_bands: matches.value_of("BANDS").unwrap().parse().expect("Error: integer parameter expected in BANDS argument"),
+            n_rows: matches.value_of("ROWS").unwrap().parse().expect("Error: integer parameter expected in ROWS argument"),
+            min_title_length: matches.value_of("MIN_TITLE_LENGTH").unwrap().parse().expect("Error: integer parameter expected in MIN_TITLE_LENGTH argument"),
+        };
+        if options.n_bands == 0 || options.n_rows == 0 {
+            panic!("Error: BANDS and ROWS arguments must be positive");
+        }
+        let it = DatasetItemIterator::<RedditPost,_>::new(inputs_filepath.into_iter().map(|s| s.to_string()));
+        let filter = get_post_filter(matches);
+        let it = filter_posts(it, &filter);
+        let clusters = get_title_clusters(it, &options);
+        save_title_clusters(&clusters, output_filepath);
+        println!("{} clusters of similar titles found", clusters.len());
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("dedup") {
+        let output_filepath = matches.value_of("OUTPUT").unwrap();
+        let inputs_filepath = matches.values_of("INPUTS").unwrap().map(|s| s.to_string()).collect();
+        let columns = match matches.value_of("COLUMNS").unwrap() {
+            "rich" => ColumnSet::Rich,
+            _ => ColumnSet::Lean,
+        };
+        let format = DatasetFormat::from_name(matches.value_of("FORMAT").unwrap()).unwrap();
+        let summary = dedup_datasets(inputs_filepath, output_filepath, columns, format);
+        if output_filepath == STDIO_PATH {
+            eprintln!("{:#?}", summary);
+        } else {
+            println!("{:#?}", summary);
+        }
     }
 }
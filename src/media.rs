@@ -0,0 +1,326 @@
+//! Identify the media (videos, images...) linked by the urls of the known hosting websites,
+//! so that the different urls of a same media share the same key
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Identity of a media, independent of the url used to link to it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MediaKey {
+    pub provider: &'static str,
+    pub media_id: String,
+}
+
+impl fmt::Display for MediaKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.provider, self.media_id)
+    }
+}
+
+/// The parts of an url used to find the media it links to
+#[derive(Debug)]
+pub struct UrlParts<'a> {
+    /// The host in lowercase, without the `www.` and `m.` prefixes
+    pub host: String,
+    /// The path, starting with a `/` if not empty
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+}
+
+impl<'a> UrlParts<'a> {
+    /// Split an url, with or without scheme
+    pub fn parse(url: &'a str) -> Self {
+        let url = url.split('#').next().unwrap();
+        let url = url.find("://").map_or(url, |i| &url[i + 3..]);
+        let (url, query) = match url.find('?') {
+            Some(i) => (&url[..i], Some(&url[i + 1..])),
+            None => (url, None),
+        };
+        let (host, path) = url.split_at(url.find('/').unwrap_or(url.len()));
+        let mut host = host.to_ascii_lowercase();
+        for prefix in ["www.", "m."].iter() {
+            if host.starts_with(prefix) {
+                host.drain(..prefix.len());
+            }
+        }
+        UrlParts { host, path, query }
+    }
+
+    /// Check if the host is the domain, or one of its subdomains
+    pub fn is_on(&self, domain: &str) -> bool {
+        self.host == domain || (self.host.ends_with(domain) && self.host[..self.host.len() - domain.len()].ends_with('.'))
+    }
+
+    /// Get the value of a query parameter
+    pub fn query_param(&self, name: &str) -> Option<&'a str> {
+        self.query?
+            .split('&')
+            .map(|param| param.strip_prefix("amp;").unwrap_or(param))
+            .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+    }
+
+    /// Get the non empty segments of the path
+    pub fn path_segments(&self) -> Vec<&'a str> {
+        self.path.split('/').filter(|s| !s.is_empty()).collect()
+    }
+}
+
+/// Find the media linked by the urls of a hosting website
+pub trait MediaExtractor: fmt::Debug + Send + Sync {
+    /// Name of the hosting website, used in the media keys
+    fn provider(&self) -> &'static str;
+
+    /// Get the id of the media linked by the url, or None if the url does not link
+    /// to a media of this provider
+    fn media_id(&self, url: &UrlParts) -> Option<String>;
+}
+
+/// Get the key of the media linked by an url, using the first extractor recognizing it
+pub fn media_key(extractors: &[Arc<dyn MediaExtractor>], url: &str) -> Option<MediaKey> {
+    let url = UrlParts::parse(url);
+    extractors.iter().find_map(|extractor| {
+        extractor.media_id(&url).map(|media_id| MediaKey {
+            provider: extractor.provider(),
+            media_id,
+        })
+    })
+}
+
+/// The extractors of the websites hosting most of the media linked on reddit
+pub fn default_extractors() -> Vec<Arc<dyn MediaExtractor>> {
+    vec![
+        Arc::new(YoutubeExtractor),
+        Arc::new(ImgurExtractor),
+        Arc::new(GfycatExtractor),
+        Arc::new(VimeoExtractor),
+        Arc::new(StreamableExtractor),
+        Arc::new(RedditMediaExtractor),
+    ]
+}
+
+/// Remove the extension of a file name
+fn strip_extension(name: &str) -> &str {
+    name.find('.').map_or(name, |i| &name[..i])
+}
+
+/// Check if an id contains only alphanumeric characters, `-` and `_`
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Videos of youtube.com and youtu.be
+#[derive(Debug)]
+pub struct YoutubeExtractor;
+
+impl MediaExtractor for YoutubeExtractor {
+    fn provider(&self) -> &'static str {
+        "youtube"
+    }
+
+    fn media_id(&self, url: &UrlParts) -> Option<String> {
+        let segments = url.path_segments();
+        let id = if url.host == "youtu.be" {
+            *segments.first()?
+        } else if url.is_on("youtube.com") || url.is_on("youtube-nocookie.com") {
+            match segments.as_slice() {
+                ["watch"] => url.query_param("v")?,
+                ["embed", id, ..] | ["v", id, ..] | ["shorts", id, ..] | ["live", id, ..] => id,
+                _ => return None,
+            }
+        } else {
+            return None;
+        };
+        // The video ids have 11 characters, some urls of the dumps have trailing garbage
+        let id = id.get(..11).unwrap_or(id);
+        if is_valid_id(id) {
+            Some(id.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Images and albums of imgur.com
+#[derive(Debug)]
+pub struct ImgurExtractor;
+
+impl MediaExtractor for ImgurExtractor {
+    fn provider(&self) -> &'static str {
+        "imgur"
+    }
+
+    fn media_id(&self, url: &UrlParts) -> Option<String> {
+        if !url.is_on("imgur.com") {
+            return None;
+        }
+        match url.path_segments().as_slice() {
+            ["a", id] | ["gallery", id] if is_valid_id(id) => Some(format!("a/{}", id)),
+            [name] => {
+                let id = strip_extension(name);
+                // The thumbnails of an image have a one letter suffix after its 5 or 7 characters id
+                let id = match id.len() {
+                    6 | 8 if id.ends_with(&['s', 'b', 't', 'm', 'l', 'h'][..]) => &id[..id.len() - 1],
+                    _ => id,
+                };
+                if is_valid_id(id) {
+                    Some(id.to_string())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Animations of gfycat.com, whose ids are case insensitive
+#[derive(Debug)]
+pub struct GfycatExtractor;
+
+impl MediaExtractor for GfycatExtractor {
+    fn provider(&self) -> &'static str {
+        "gfycat"
+    }
+
+    fn media_id(&self, url: &UrlParts) -> Option<String> {
+        if !url.is_on("gfycat.com") {
+            return None;
+        }
+        let name = *url.path_segments().last()?;
+        // The files of thumbs.gfycat.com have suffixes like `-size_restricted.gif`
+        let id = strip_extension(name).split('-').next().unwrap();
+        if is_valid_id(id) {
+            Some(id.to_ascii_lowercase())
+        } else {
+            None
+        }
+    }
+}
+
+/// Videos of vimeo.com
+#[derive(Debug)]
+pub struct VimeoExtractor;
+
+impl MediaExtractor for VimeoExtractor {
+    fn provider(&self) -> &'static str {
+        "vimeo"
+    }
+
+    fn media_id(&self, url: &UrlParts) -> Option<String> {
+        if !url.is_on("vimeo.com") {
+            return None;
+        }
+        url.path_segments()
+            .into_iter()
+            .find(|segment| segment.chars().all(|c| c.is_ascii_digit()))
+            .map(str::to_string)
+    }
+}
+
+/// Videos of streamable.com
+#[derive(Debug)]
+pub struct StreamableExtractor;
+
+impl MediaExtractor for StreamableExtractor {
+    fn provider(&self) -> &'static str {
+        "streamable"
+    }
+
+    fn media_id(&self, url: &UrlParts) -> Option<String> {
+        if !url.is_on("streamable.com") {
+            return None;
+        }
+        match url.path_segments().as_slice() {
+            [id] | ["e", id] | ["o", id] if is_valid_id(id) => Some(id.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Images and videos hosted by reddit, on i.redd.it and v.redd.it
+#[derive(Debug)]
+pub struct RedditMediaExtractor;
+
+impl MediaExtractor for RedditMediaExtractor {
+    fn provider(&self) -> &'static str {
+        "reddit"
+    }
+
+    fn media_id(&self, url: &UrlParts) -> Option<String> {
+        if url.host != "i.redd.it" && url.host != "v.redd.it" {
+            return None;
+        }
+        let id = strip_extension(url.path_segments().first()?);
+        if is_valid_id(id) {
+            Some(id.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(url: &str) -> Option<String> {
+        media_key(&default_extractors(), url).map(|key| key.to_string())
+    }
+
+    /// The different urls of a same media have the same key
+    #[test]
+    fn media_keys() {
+        let same_media = [
+            (
+                "youtube:dQw4w9WgXcQ",
+                &[
+                    "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                    "http://youtu.be/dQw4w9WgXcQ",
+                    "https://m.youtube.com/watch?v=dQw4w9WgXcQ&feature=related",
+                    "https://www.youtube.com/watch?feature=player_embedded&amp;v=dQw4w9WgXcQ",
+                    "https://www.youtube.com/embed/dQw4w9WgXcQ?autoplay=1",
+                    "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ",
+                    "youtube.com/v/dQw4w9WgXcQ",
+                ][..],
+            ),
+            (
+                "imgur:AbCdEfG",
+                &[
+                    "https://imgur.com/AbCdEfG",
+                    "http://i.imgur.com/AbCdEfG.jpg",
+                    "https://i.imgur.com/AbCdEfGh.png",
+                    "https://m.imgur.com/AbCdEfG",
+                ][..],
+            ),
+            ("imgur:a/xYz12", &["https://imgur.com/a/xYz12", "https://imgur.com/gallery/xYz12"][..]),
+            (
+                "gfycat:happyfluffydog",
+                &[
+                    "https://gfycat.com/HappyFluffyDog",
+                    "https://giant.gfycat.com/HappyFluffyDog.webm",
+                    "https://thumbs.gfycat.com/HappyFluffyDog-size_restricted.gif",
+                    "https://gfycat.com/gifs/detail/HappyFluffyDog",
+                ][..],
+            ),
+            ("vimeo:123456", &["https://vimeo.com/123456", "https://player.vimeo.com/video/123456"][..]),
+            ("streamable:abc12", &["https://streamable.com/abc12", "https://streamable.com/e/abc12"][..]),
+            ("reddit:a1b2c3", &["https://i.redd.it/a1b2c3.jpg", "https://v.redd.it/a1b2c3"][..]),
+        ];
+        for (expected, urls) in same_media.iter() {
+            for url in urls.iter() {
+                assert_eq!(key(url).as_deref(), Some(*expected), "{}", url);
+            }
+        }
+
+        let not_media = [
+            "https://www.youtube.com/user/someone",
+            "https://www.youtube.com/",
+            "https://notyoutube.com/watch?v=dQw4w9WgXcQ",
+            "https://imgur.com/",
+            "https://example.com/AbCdEfG.jpg",
+        ];
+        for url in not_media.iter() {
+            assert_eq!(key(url), None, "{}", url);
+        }
+    }
+}
@@ -1,88 +1,254 @@
 //! Get the possible fields taken by multiple JSON objects
 
+use crate::read_files::open_file;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter};
 
 /// Different types a JSON value can take
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize)]
 pub enum ValueType {
     Null,
     Bool,
     Number,
     String,
     Array,
+    Object,
 }
 
 /// Possible types taken by multiple JSON objects
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PossibleTypes {
-    possible_primitive_types: HashSet<ValueType>,
-    possible_fields: HashMap<String, PossibleTypes>,
+    /// Number of values seen for each type
+    pub type_counts: HashMap<ValueType, u64>,
+    /// Possible types of the fields of the objects
+    pub possible_fields: HashMap<String, PossibleTypes>,
+    /// Possible types of the elements of the arrays
+    pub array_elements: Option<Box<PossibleTypes>>,
+    /// Number of numbers seen that are not integers
+    pub n_floats: u64,
 }
 
 impl PossibleTypes {
     /// Create a new set of possible types
     pub fn new() -> PossibleTypes {
         PossibleTypes {
-            possible_primitive_types: HashSet::new(),
+            type_counts: HashMap::new(),
             possible_fields: HashMap::new(),
+            array_elements: None,
+            n_floats: 0,
         }
     }
 
+    /// Number of values seen, including the null values
+    pub fn n_present(&self) -> u64 {
+        self.type_counts.values().sum()
+    }
+
+    /// Number of values seen with the given type
+    pub fn n_type(&self, value_type: ValueType) -> u64 {
+        *self.type_counts.get(&value_type).unwrap_or(&0)
+    }
+
+    /// Number of times the value was missing, given the number of values seen for its parent.
+    /// For a field, the parent values are the objects, and for array elements, the elements.
+    pub fn n_missing(&self, n_parents: u64) -> u64 {
+        n_parents - self.n_present()
+    }
+
+    /// Check if the value can be null or missing, given the number of values seen for its parent
+    pub fn is_nullable(&self, n_parents: u64) -> bool {
+        self.n_type(ValueType::Null) > 0 || self.n_missing(n_parents) > 0
+    }
+
     /// Add a type to the list of types possibilities
     pub fn add_type(&mut self, val: Value) {
-        match val {
-            Value::Null => {
-                self.possible_primitive_types.insert(ValueType::Null);
-            }
-            Value::Bool(_) => {
-                self.possible_primitive_types.insert(ValueType::Bool);
-            }
-            Value::Number(_) => {
-                self.possible_primitive_types.insert(ValueType::Number);
-            }
-            Value::String(_) => {
-                self.possible_primitive_types.insert(ValueType::String);
+        let value_type = match val {
+            Value::Null => ValueType::Null,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Number(number) => {
+                if number.is_f64() {
+                    self.n_floats += 1;
+                }
+                ValueType::Number
             }
-            Value::Array(_) => {
-                self.possible_primitive_types.insert(ValueType::Array);
+            Value::String(_) => ValueType::String,
+            Value::Array(values) => {
+                let elements = self
+                    .array_elements
+                    .get_or_insert_with(|| Box::new(PossibleTypes::new()));
+                for value in values {
+                    elements.add_type(value);
+                }
+                ValueType::Array
             }
             Value::Object(map) => {
-                // Add null option for evey fields not contained by this example
-                for (key, value) in self.possible_fields.iter_mut() {
-                    //TODO only works if last element don't introduce new fields
-                    if !map.contains_key(key) {
-                        value.add_type(Value::Null);
-                    }
-                }
+                // The fields not contained by this object don't need to be updated,
+                // since the number of missing values is deduced from the number of objects
                 for (key, value) in map.into_iter() {
-                    if let Some(possible_vals_field) = self.possible_fields.get_mut(&key) {
-                        possible_vals_field.add_type(value);
-                    } else {
-                        let mut possible_vals_field = PossibleTypes::new();
-                        possible_vals_field.add_type(value);
-                        self.possible_fields.insert(key, possible_vals_field);
-                    }
+                    self.possible_fields
+                        .entry(key)
+                        .or_insert_with(PossibleTypes::new)
+                        .add_type(value);
                 }
+                ValueType::Object
             }
+        };
+        *self.type_counts.entry(value_type).or_insert(0) += 1;
+    }
+
+    /// Write the possible types as a tree, where each field is on its own line.
+    /// `n_parents` is the number of values seen for the parent.
+    fn write_tree(
+        &self,
+        f: &mut fmt::Formatter,
+        name: &str,
+        depth: usize,
+        n_parents: u64,
+    ) -> fmt::Result {
+        let mut types: Vec<_> = self.type_counts.iter().collect();
+        types.sort();
+        let types: Vec<_> = types
+            .iter()
+            .map(|(t, n)| format!("{:?} {}", t, n))
+            .collect();
+        let n_present = self.n_present();
+        let n_null = self.n_type(ValueType::Null);
+        writeln!(
+            f,
+            "{:indent$}{}{}: {} (present {}/{} {:.1}%, null {} {:.1}%)",
+            "",
+            name,
+            if self.is_nullable(n_parents) { "?" } else { "" },
+            types.join(" | "),
+            n_present,
+            n_parents,
+            percentage(n_present, n_parents),
+            n_null,
+            percentage(n_null, n_present),
+            indent = 2 * depth
+        )?;
+        let n_objects = self.n_type(ValueType::Object);
+        let mut fields: Vec<_> = self.possible_fields.iter().collect();
+        fields.sort_by_key(|(name, _)| *name);
+        for (name, field) in fields {
+            field.write_tree(f, name, depth + 1, n_objects)?;
         }
+        if let Some(elements) = &self.array_elements {
+            elements.write_tree(f, "[]", depth + 1, elements.n_present())?;
+        }
+        Ok(())
     }
 }
 
-/// Get the set of all possible types taken by JSON objects stored in the file
-/// The file is expected to have one JSON object per line
-#[allow(dead_code)]
-pub fn get_all_possible_types(file: File) -> PossibleTypes {
-    let f = BufReader::new(file);
+/// Get the type of a JSON value
+pub fn value_type(value: &Value) -> ValueType {
+    match value {
+        Value::Null => ValueType::Null,
+        Value::Bool(_) => ValueType::Bool,
+        Value::Number(_) => ValueType::Number,
+        Value::String(_) => ValueType::String,
+        Value::Array(_) => ValueType::Array,
+        Value::Object(_) => ValueType::Object,
+    }
+}
+
+/// Save possible types in a JSON file, to use them as a schema
+pub fn save_possible_types(possible_types: &PossibleTypes, filepath: &str) {
+    let file = BufWriter::new(File::create(filepath).unwrap());
+    serde_json::to_writer(file, possible_types).unwrap();
+}
 
+/// Load possible types saved in a JSON file
+pub fn load_possible_types(filepath: &str) -> PossibleTypes {
+    let file = BufReader::new(File::open(filepath).unwrap());
+    serde_json::from_reader(file).unwrap()
+}
+
+/// Get the percentage represented by a part of a total
+fn percentage(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        100.0 * part as f64 / total as f64
+    }
+}
+
+impl fmt::Display for PossibleTypes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_tree(f, "<root>", 0, self.n_present())
+    }
+}
+
+/// Get the set of all possible types taken by JSON objects stored in the files.
+/// The files are expected to have one JSON object per line,
+/// and only the first `max_lines` lines of each file are read if given.
+/// Also return the number of lines that are not valid JSON.
+pub fn get_all_possible_types(filepaths: &[&str], max_lines: Option<usize>) -> (PossibleTypes, u64) {
     let mut possible_vals = PossibleTypes::new();
-    for line in f.lines() {
-        let line = line.unwrap();
-        let result: Value = serde_json::from_str(&line).unwrap();
-        possible_vals.add_type(result);
+    let mut n_invalid_lines = 0;
+    for filepath in filepaths {
+        let lines = open_file(filepath).split(b'\n').take(max_lines.unwrap_or(usize::MAX));
+        for line in lines {
+            let line = line.unwrap();
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            match serde_json::from_slice::<Value>(&line) {
+                Ok(value) => possible_vals.add_type(value),
+                Err(_) => n_invalid_lines += 1,
+            }
+        }
+    }
+    (possible_vals, n_invalid_lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(lines: &[&str]) -> PossibleTypes {
+        let mut possible_types = PossibleTypes::new();
+        for line in lines {
+            possible_types.add_type(serde_json::from_str(line).unwrap());
+        }
+        possible_types
+    }
+
+    /// A field introduced after the first objects should be nullable.
+    #[test]
+    fn late_introduced_field() {
+        let types = infer(&[r#"{"a":1}"#, r#"{"a":2}"#, r#"{"a":3,"b":"x"}"#]);
+        let a = &types.possible_fields["a"];
+        let b = &types.possible_fields["b"];
+        assert!(!a.is_nullable(3));
+        assert!(b.is_nullable(3));
+        assert_eq!(b.n_missing(3), 2);
+        assert_eq!(b.n_type(ValueType::Null), 0);
+    }
+
+    /// Count the occurrences of each type, and the types of array elements.
+    #[test]
+    fn type_counts_and_arrays() {
+        let types = infer(&[
+            r#"{"a":null,"l":[1,2]}"#,
+            r#"{"a":1,"l":["x"]}"#,
+            r#"{"a":"1","l":[]}"#,
+            r#"{"a":2,"l":null}"#,
+        ]);
+        let a = &types.possible_fields["a"];
+        assert_eq!(a.n_type(ValueType::Null), 1);
+        assert_eq!(a.n_type(ValueType::Number), 2);
+        assert_eq!(a.n_type(ValueType::String), 1);
+        let l = &types.possible_fields["l"];
+        assert_eq!(l.n_type(ValueType::Array), 3);
+        let elements = l.array_elements.as_ref().unwrap();
+        assert_eq!(elements.n_type(ValueType::Number), 2);
+        assert_eq!(elements.n_type(ValueType::String), 1);
+        assert_eq!(elements.n_present(), 3);
     }
-    possible_vals
 }
@@ -5,6 +5,99 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::io::{BufReader, Lines};
 use std::marker::PhantomData;
+use std::path::Path;
+
+/// File path meaning the standard input, or the standard output
+pub const STDIO_PATH: &str = "-";
+
+/// Compression formats used by the pushshift dumps
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zstd,
+    Xz,
+    Bzip2,
+    Gzip,
+}
+
+/// Maximum window log accepted by the zstd decoder.
+/// The recent pushshift dumps are compressed with `--long=31`.
+const ZSTD_WINDOW_LOG_MAX: u32 = 31;
+
+impl Compression {
+    /// Get the compression format from the file extension
+    pub fn from_extension(filepath: &str) -> Option<Compression> {
+        let extension = Path::new(filepath).extension()?.to_str()?;
+        match extension {
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "xz" => Some(Compression::Xz),
+            "bz2" => Some(Compression::Bzip2),
+            "gz" => Some(Compression::Gzip),
+            _ => None,
+        }
+    }
+
+    /// Get the compression format from the first bytes of a file
+    pub fn from_magic_bytes(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if bytes.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Wrap a reader into a decompressing reader
+pub fn decompress<R: BufRead + Send + 'static>(
+    reader: R,
+    compression: Compression,
+) -> Box<dyn BufRead + Send> {
+    match compression {
+        Compression::None => Box::new(reader),
+        Compression::Zstd => {
+            let mut decoder = zstd::Decoder::with_buffer(reader).unwrap();
+            decoder.window_log_max(ZSTD_WINDOW_LOG_MAX).unwrap();
+            Box::new(BufReader::new(decoder))
+        }
+        Compression::Xz => Box::new(BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(
+            reader,
+        ))),
+        Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+    }
+}
+
+/// Open a file, or the standard input if the path is `-`, and decompress it while reading if it is compressed.
+/// The compression is detected from the file extension, or from the magic
+/// bytes if the extension is unknown.
+pub fn open_file(filepath: &str) -> Box<dyn BufRead + Send> {
+    let file: Box<dyn Read + Send> = if filepath == STDIO_PATH {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(File::open(filepath).unwrap_or_else(|e| panic!("Cannot open {}: {}", filepath, e)))
+    };
+    let mut reader = BufReader::new(file);
+    let compression = match Compression::from_extension(filepath) {
+        Some(compression) => compression,
+        None => Compression::from_magic_bytes(reader.fill_buf().unwrap()),
+    };
+    decompress(reader, compression)
+}
+
+/// Create a file, or get the standard output if the path is `-`
+pub fn create_file(filepath: &str) -> Box<dyn Write + Send> {
+    if filepath == STDIO_PATH {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(File::create(filepath).unwrap_or_else(|e| panic!("Cannot create {}: {}", filepath, e)))
+    }
+}
 
 /// An iterator iterating through multiple files,
 /// to deserialize JSON objects into a given struct
@@ -14,7 +107,7 @@ where
     S: DeserializeOwned,
 {
     filepath_iterator: FPI,
-    current_reader: Option<Lines<BufReader<File>>>,
+    current_reader: Option<Lines<Box<dyn BufRead + Send>>>,
     json_struct_type: std::marker::PhantomData<S>,
 }
 
@@ -64,9 +157,7 @@ where
             }
         }
         if let Some(filepath) = self.filepath_iterator.next() {
-            let file = File::open(filepath).unwrap();
-            let buf_reader = BufReader::new(file);
-            self.current_reader = Some(buf_reader.lines());
+            self.current_reader = Some(open_file(&filepath).lines());
             self.next()
         } else {
             None
@@ -74,68 +165,73 @@ where
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
 
-/// An iterator iterating through multiple files,
-/// to deserialize CSV objects into a given struct
-pub struct CSVItemIterator<S, FPI>
-where
-    FPI: Iterator<Item = String>,
-    S: DeserializeOwned,
-{
-    filepath_iterator: FPI,
-    current_reader: Option<csv::DeserializeRecordsIntoIter<File, S>>,
-    json_struct_type: std::marker::PhantomData<S>,
-}
+    const LINES: &str = "{\"id\":\"a\"}\n{\"id\":\"b\"}\n";
 
-impl<S, FPI> Clone for CSVItemIterator<S, FPI>
-where
-    FPI: Iterator<Item = String> + Clone,
-    S: DeserializeOwned,
-{
-    fn clone(&self) -> Self {
-        assert!(self.current_reader.is_none());
-        CSVItemIterator {
-            filepath_iterator: self.filepath_iterator.clone(),
-            current_reader: None,
-            json_struct_type: self.json_struct_type,
+    fn compress(compression: Compression) -> Vec<u8> {
+        let mut output = vec![];
+        match compression {
+            Compression::None => output.extend_from_slice(LINES.as_bytes()),
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(&mut output, 3).unwrap();
+                encoder.long_distance_matching(true).unwrap();
+                encoder.window_log(ZSTD_WINDOW_LOG_MAX).unwrap();
+                encoder.write_all(LINES.as_bytes()).unwrap();
+                encoder.finish().unwrap();
+            }
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(&mut output, 6);
+                encoder.write_all(LINES.as_bytes()).unwrap();
+                encoder.finish().unwrap();
+            }
+            Compression::Bzip2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(&mut output, bzip2::Compression::default());
+                encoder.write_all(LINES.as_bytes()).unwrap();
+                encoder.finish().unwrap();
+            }
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(&mut output, flate2::Compression::default());
+                encoder.write_all(LINES.as_bytes()).unwrap();
+                encoder.finish().unwrap();
+            }
         }
+        output
     }
-}
 
-impl<S, FPI> CSVItemIterator<S, FPI>
-where
-    FPI: Iterator<Item = String>,
-    S: DeserializeOwned,
-{
-    /// Create a new iterator, given an iterator over file paths
-    #[allow(dead_code)]
-    pub fn new(filepath_iterator: FPI) -> Self {
-        Self {
-            filepath_iterator,
-            current_reader: None,
-            json_struct_type: PhantomData,
+    /// Decompress data in every format, detecting it from the magic bytes.
+    #[test]
+    fn decompress_from_magic_bytes() {
+        for &compression in [
+            Compression::None,
+            Compression::Zstd,
+            Compression::Xz,
+            Compression::Bzip2,
+            Compression::Gzip,
+        ]
+        .iter()
+        {
+            let data = compress(compression);
+            assert_eq!(Compression::from_magic_bytes(&data), compression);
+            let reader = decompress(Cursor::new(data), compression);
+            let lines: Vec<_> = reader.lines().map(Result::unwrap).collect();
+            assert_eq!(lines, vec!["{\"id\":\"a\"}", "{\"id\":\"b\"}"]);
         }
     }
-}
 
-impl<S, FPI> Iterator for CSVItemIterator<S, FPI>
-where
-    FPI: Iterator<Item = String>,
-    S: DeserializeOwned,
-{
-    type Item = S;
-
-    fn next(&mut self) -> Option<S> {
-        if let Some(reader) = &mut self.current_reader {
-            if let Some(csv_item) = reader.next() {
-                return Some(csv_item.unwrap());
-            }
-        }
-        if let Some(filepath) = self.filepath_iterator.next() {
-            self.current_reader = Some(csv::Reader::from_path(filepath).unwrap().into_deserialize());
-            self.next()
-        } else {
-            None
-        }
+    #[test]
+    fn compression_from_extension() {
+        assert_eq!(Compression::from_extension("RS_2019-01.zst"), Some(Compression::Zstd));
+        assert_eq!(Compression::from_extension("RS_2017-11.xz"), Some(Compression::Xz));
+        assert_eq!(Compression::from_extension("RS_2011-01.bz2"), Some(Compression::Bzip2));
+        assert_eq!(Compression::from_extension("dump.gz"), Some(Compression::Gzip));
+        assert_eq!(Compression::from_extension("RS_2011-01"), None);
+        // The standard input is decompressed according to its magic bytes
+        assert_eq!(Compression::from_extension(STDIO_PATH), None);
     }
 }
@@ -0,0 +1,38 @@
+//! Contain structs representing a reddit comment.
+
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/// A struct representing the interesting fields of a reddit comment.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RedditComment {
+    pub id: String,
+    pub link_id: String,
+    pub parent_id: String,
+    pub subreddit: String,
+    pub author: String,
+    pub score: i32,
+    pub created_utc: i64,
+}
+
+impl RedditComment {
+    /// Get the id of the submission the comment was posted in.
+    /// The `link_id` field is the fullname of the submission (`t3_<id>`).
+    pub fn get_submission_id(&self) -> &str {
+        self.link_id.strip_prefix("t3_").unwrap_or(&self.link_id)
+    }
+}
+
+impl PartialEq for RedditComment {
+    fn eq(&self, other: &RedditComment) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for RedditComment {}
+
+impl Hash for RedditComment {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
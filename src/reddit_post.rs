@@ -1,15 +1,19 @@
 //! Contain structs representing a reddit post.
 
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
 
 /// A struct representing the interesting fields of a reddit post.
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct RedditPost {
     pub href_url: Option<String>,
-    pub num_comments: i32,
+    pub num_comments: u64,
     pub score: i32,
-    pub gilded: i32,
+    #[serde(default)]
+    pub gilded: u64,
     pub subreddit: String,
     pub id: String,
     pub title: String,
@@ -18,17 +22,195 @@ pub struct RedditPost {
     pub author_cakeday: Option<bool>,
     pub permalink: String,
     pub author: String,
-    pub created_utc: i32,
+    pub created_utc: i64,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub is_self: Option<bool>,
+    #[serde(default)]
+    pub selftext: Option<String>,
+    #[serde(default)]
+    pub crosspost_parent: Option<String>,
+    #[serde(default)]
+    pub link_flair_text: Option<String>,
+    #[serde(default)]
+    pub num_crossposts: Option<u64>,
+    /// Date at which the post was scraped, to keep the freshest record of a post
+    #[serde(default)]
+    pub retrieved_on: Option<i64>,
+}
+
+/// A reddit post written with the lean set of columns.
+/// The lean columns are the fields of `RedditPost` that are not optional, and `retrieved_on`
+/// used to deduplicate the posts, so the lean datasets can be read as `RedditPost`.
+#[derive(Debug, Clone)]
+pub struct LeanRedditPost(RedditPost);
+
+/// Serialize the listed fields of the post
+macro_rules! serialize_fields {
+    ($serializer:expr, $post:expr, $($field:ident),*) => {{
+        let mut state = $serializer.serialize_struct("LeanRedditPost", [$(stringify!($field)),*].len())?;
+        $(state.serialize_field(stringify!($field), &$post.$field)?;)*
+        state.end()
+    }};
+}
+
+impl Serialize for LeanRedditPost {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_fields!(
+            serializer,
+            self.0,
+            href_url,
+            num_comments,
+            score,
+            gilded,
+            subreddit,
+            id,
+            title,
+            url,
+            over_18,
+            author_cakeday,
+            permalink,
+            author,
+            created_utc,
+            retrieved_on
+        )
+    }
+}
+
+impl From<LeanRedditPost> for RedditPost {
+    /// Get the post without the optional fields that are not in the lean columns
+    fn from(post: LeanRedditPost) -> Self {
+        RedditPost {
+            domain: None,
+            is_self: None,
+            selftext: None,
+            crosspost_parent: None,
+            link_flair_text: None,
+            num_crossposts: None,
+            ..post.0
+        }
+    }
+}
+
+/// A post written in a simplified dataset, with the lean or the rich set of columns
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum PostRecord {
+    Rich(RedditPost),
+    Lean(LeanRedditPost),
 }
 
 impl RedditPost {
+    /// Get the post, serialized with the lean set of columns
+    pub fn into_lean(self) -> LeanRedditPost {
+        LeanRedditPost(self)
+    }
+
+    /// Check if the post is a self post, that does not link to an url.
+    /// The `is_self` and `domain` fields are used when they are present,
+    /// otherwise the url is compared to the permalink, whatever the reddit host.
+    pub fn is_self_post(&self) -> bool {
+        if let Some(is_self) = self.is_self {
+            return is_self;
+        }
+        if let Some(domain) = &self.domain {
+            if let Some(subreddit) = domain.strip_prefix("self.") {
+                return subreddit.eq_ignore_ascii_case(&self.subreddit);
+            }
+        }
+        match (reddit_path(&self.url), reddit_path(&self.permalink)) {
+            (Some(url_path), Some(permalink_path)) => url_path.eq_ignore_ascii_case(permalink_path),
+            _ => false,
+        }
+    }
+
+    /// Get the url linked by the post, or None if it is a self post
     pub fn get_linked_url(&self) -> Option<String> {
-        if self.url.as_bytes().len() == 22 + self.permalink.as_bytes().len() {
-            if self.url.as_bytes()[22..] == self.permalink.as_bytes()[..] {
-                return None;
+        if self.is_self_post() {
+            None
+        } else {
+            Some(self.url.clone())
+        }
+    }
+
+    /// Get the http(s) links found in the markdown text of the post, without duplicates
+    pub fn get_selftext_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = vec![];
+        if let Some(selftext) = &self.selftext {
+            for url in markdown_url_regex().find_iter(selftext) {
+                let url = trim_markdown_url(url.as_str());
+                if !urls.iter().any(|u| u == url) {
+                    urls.push(url.to_string());
+                }
             }
         }
-        Some(self.url.clone())
+        urls
+    }
+
+    /// Get the urls linked by the post.
+    /// If `include_selftext` is true, the links found in the text of self posts are also returned.
+    pub fn get_linked_urls(&self, include_selftext: bool) -> Vec<String> {
+        match self.get_linked_url() {
+            Some(url) => vec![url],
+            None if include_selftext => self.get_selftext_urls(),
+            None => vec![],
+        }
+    }
+
+    /// Get the id of the submission this post was crossposted from, without the `t3_` prefix
+    pub fn get_crosspost_parent_id(&self) -> Option<&str> {
+        let parent = self.crosspost_parent.as_deref()?;
+        Some(parent.strip_prefix("t3_").unwrap_or(parent))
+    }
+}
+
+/// Get the path of a reddit url, without the query, the fragment and the trailing slash.
+/// The url can be relative, or absolute with any reddit host (`old.reddit.com`, `np.reddit.com`...).
+/// Return None if the url does not point to reddit.
+fn reddit_path(url: &str) -> Option<&str> {
+    let path = if url.starts_with('/') && !url.starts_with("//") {
+        url
+    } else {
+        let without_scheme = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .or_else(|| url.strip_prefix("//"))?;
+        let host_end = without_scheme.find('/').unwrap_or(without_scheme.len());
+        let host = without_scheme[..host_end].to_ascii_lowercase();
+        let host = host.split(':').next().unwrap();
+        if host != "reddit.com" && !host.ends_with(".reddit.com") {
+            return None;
+        }
+        &without_scheme[host_end..]
+    };
+    let path = &path[..path.find(&['?', '#'][..]).unwrap_or(path.len())];
+    Some(path.trim_end_matches('/'))
+}
+
+/// Regex matching the http(s) links in a markdown text.
+/// Links end at whitespaces, and at the characters delimiting markdown links and autolinks.
+fn markdown_url_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r#"(?i)https?://[^\s<>\[\]"'`|\\]+"#).unwrap())
+}
+
+/// Remove the characters that end a link found in a markdown text without being part of it,
+/// like the punctuation, or the parenthesis closing a markdown link.
+fn trim_markdown_url(mut url: &str) -> &str {
+    loop {
+        let trimmed = url.trim_end_matches(&['.', ',', ';', ':', '!', '?', '*', '~'][..]);
+        let trimmed = if trimmed.ends_with(')')
+            && trimmed.matches('(').count() < trimmed.matches(')').count()
+        {
+            &trimmed[..trimmed.len() - 1]
+        } else {
+            trimmed
+        };
+        if trimmed == url {
+            return url;
+        }
+        url = trimmed;
     }
 }
 
@@ -46,9 +228,37 @@ impl Hash for RedditPost {
     }
 }
 
+/// Create a post for the tests, linking to an url.
+/// The other fields can be changed with the struct update syntax.
+#[cfg(test)]
+pub fn test_post(id: &str, subreddit: &str, url: &str, created_utc: i64) -> RedditPost {
+    RedditPost {
+        href_url: None,
+        num_comments: 0,
+        score: 1,
+        gilded: 0,
+        subreddit: subreddit.to_string(),
+        id: id.to_string(),
+        title: "t".to_string(),
+        url: url.to_string(),
+        over_18: false,
+        author_cakeday: None,
+        permalink: format!("/r/{}/comments/{}/t/", subreddit, id),
+        author: "a".to_string(),
+        created_utc,
+        domain: None,
+        is_self: None,
+        selftext: None,
+        crosspost_parent: None,
+        link_flair_text: None,
+        num_crossposts: None,
+        retrieved_on: None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::RedditPost;
+    use super::{test_post, RedditPost};
     use serde_json::Result;
 
     /// Try to deserialize a comment sampled from the dataset.
@@ -59,4 +269,80 @@ mod tests {
         //assert!(c.is_ok());
         c.unwrap();
     }
+
+    /// Detect the self posts, with the url variants found across the dumps
+    #[test]
+    fn self_post_detection() {
+        let mut post = test_post("s1", "AskReddit", "", 1);
+        let self_urls = [
+            "https://www.reddit.com/r/AskReddit/comments/s1/t/",
+            "http://www.reddit.com/r/AskReddit/comments/s1/t/",
+            "http://reddit.com/r/AskReddit/comments/s1/t/",
+            "https://old.reddit.com/r/AskReddit/comments/s1/t/",
+            "https://np.reddit.com/r/AskReddit/comments/s1/t",
+            "https://WWW.Reddit.com/r/askreddit/comments/s1/t/?ref=search_posts",
+            "/r/AskReddit/comments/s1/t/",
+        ];
+        for url in self_urls.iter() {
+            post.url = url.to_string();
+            assert!(post.is_self_post(), "{} should be a self post", url);
+            assert_eq!(post.get_linked_url(), None);
+        }
+        let linked_urls = [
+            "https://www.reddit.com/r/AskReddit/comments/s2/other/",
+            "https://www.notreddit.com/r/AskReddit/comments/s1/t/",
+            "https://i.imgur.com/aaaaaaa.jpg",
+        ];
+        for url in linked_urls.iter() {
+            post.url = url.to_string();
+            assert!(!post.is_self_post(), "{} should not be a self post", url);
+        }
+
+        // An absolute permalink, as found in some dumps
+        post.permalink = "http://www.reddit.com/r/AskReddit/comments/s1/t/".to_string();
+        post.url = "https://old.reddit.com/r/AskReddit/comments/s1/t/".to_string();
+        assert!(post.is_self_post());
+
+        // The is_self and domain fields take precedence over the url
+        post.url = "https://i.imgur.com/aaaaaaa.jpg".to_string();
+        post.domain = Some("self.askreddit".to_string());
+        assert!(post.is_self_post());
+        post.is_self = Some(false);
+        assert!(!post.is_self_post());
+    }
+
+    /// Extract the links of the text of a self post
+    #[test]
+    fn selftext_urls() {
+        let mut post = test_post("s1", "pics", "https://www.reddit.com/r/pics/comments/s1/t/", 1);
+        post.selftext = Some(
+            "See [this](https://en.wikipedia.org/wiki/Rust_(programming_language)) and \
+             <http://example.com/a?b=1&amp;c=2>, or https://example.com/b.\n\
+             Also **https://example.com/c** and [again](https://example.com/b)."
+                .to_string(),
+        );
+        assert_eq!(post.get_linked_urls(false), Vec::<String>::new());
+        assert_eq!(
+            post.get_linked_urls(true),
+            vec![
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+                "http://example.com/a?b=1&amp;c=2",
+                "https://example.com/b",
+                "https://example.com/c",
+            ]
+        );
+    }
+
+    /// Check that a CSV file written with the lean columns, or before the optional fields
+    /// were introduced, can still be read.
+    #[test]
+    fn read_lean_csv() {
+        let data = "href_url,num_comments,score,gilded,subreddit,id,title,url,over_18,author_cakeday,permalink,author,created_utc\n\
+                    ,2,5,0,pics,a1,t,http://x.com/1,false,,/r/pics/comments/a1/t/,u,10\n";
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let posts: Vec<RedditPost> = reader.deserialize().map(|post| post.unwrap()).collect();
+        assert_eq!(posts[0].id, "a1");
+        assert_eq!(posts[0].domain, None);
+        assert_eq!(posts[0].num_crossposts, None);
+    }
 }
@@ -0,0 +1,139 @@
+//! Resolution of the links of url shorteners, with a table mapping them to their expanded url
+
+use crate::media::UrlParts;
+use crate::reddit_post::RedditPost;
+use crate::url_canonicalization::UrlCanonicalizer;
+use crate::url_keys::UrlKeyer;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::OnceLock;
+
+/// Hosts of the usual url shorteners
+pub const SHORTENER_HOSTS: [&str; 16] = [
+    "bit.ly",
+    "bitly.com",
+    "j.mp",
+    "goo.gl",
+    "t.co",
+    "tinyurl.com",
+    "ow.ly",
+    "is.gd",
+    "buff.ly",
+    "tiny.cc",
+    "dlvr.it",
+    "fb.me",
+    "wp.me",
+    "lnkd.in",
+    "amzn.to",
+    "trib.al",
+];
+
+/// Check if an url is a link of a known url shortener
+pub fn is_short_url(url: &str) -> bool {
+    SHORTENER_HOSTS.contains(&UrlParts::parse(url).host.as_str())
+}
+
+/// Table of the expanded urls of short urls.
+/// The short urls are compared in their canonical form.
+#[derive(Debug, Clone, Default)]
+pub struct ShortUrlTable {
+    urls: HashMap<String, String>,
+    /// Hosts of the short urls of the table, to canonicalize only the urls that can be in it
+    hosts: HashSet<String>,
+}
+
+impl ShortUrlTable {
+    /// Add the expanded url of a short url
+    pub fn insert(&mut self, short_url: &str, expanded_url: &str) {
+        self.hosts.insert(UrlParts::parse(short_url).host);
+        self.urls.insert(short_url_key(short_url), expanded_url.to_string());
+    }
+
+    /// Get the expanded url of a short url, if it is in the table
+    pub fn resolve(&self, url: &str) -> Option<&str> {
+        if !self.hosts.contains(&UrlParts::parse(url).host) {
+            return None;
+        }
+        self.urls.get(&short_url_key(url)).map(String::as_str)
+    }
+}
+
+/// Get the form of a short url used in the table
+fn short_url_key(url: &str) -> String {
+    static CANONICALIZER: OnceLock<UrlCanonicalizer> = OnceLock::new();
+    CANONICALIZER.get_or_init(UrlCanonicalizer::all).canonicalize(url)
+}
+
+/// Load a table of short urls from a file.
+/// Each line contains a short url and its expanded url, separated by a tab.
+pub fn load_short_url_table(filepath: &str) -> ShortUrlTable {
+    let file = File::open(filepath).unwrap_or_else(|e| panic!("Error: cannot open the short urls file {}: {}", filepath, e));
+    let mut table = ShortUrlTable::default();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut columns = line.split('\t');
+        match (columns.next(), columns.next()) {
+            (Some(short_url), Some(expanded_url)) => table.insert(short_url.trim(), expanded_url.trim()),
+            _ => panic!("Error: expected a short url and an expanded url at line {} of {}", i + 1, filepath),
+        }
+    }
+    table
+}
+
+/// Count the links of url shorteners that are not resolved by the keyer,
+/// and get the n_urls most common ones, in their canonical form
+pub fn get_unresolved_short_urls<IT>(iterator: IT, keyer: &UrlKeyer, n_urls: usize) -> Vec<(String, u64)>
+where
+    IT: Iterator<Item = RedditPost>,
+{
+    let mut counts = HashMap::<String, u64>::new();
+    for post in iterator {
+        for url in post.get_linked_urls(keyer.selftext_urls) {
+            if is_short_url(&url) && keyer.short_urls.resolve(&url).is_none() {
+                *counts.entry(short_url_key(&url)).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(url1, n1), (url2, n2)| n2.cmp(n1).then_with(|| url1.cmp(url2)));
+    counts.truncate(n_urls);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reddit_post::test_post;
+
+    /// Resolve the short urls of a table file, and report the other ones
+    #[test]
+    fn resolve_short_urls() {
+        let filepath = std::env::temp_dir().join("reddit_reposts_short_urls.tsv");
+        std::fs::write(&filepath, "http://bit.ly/abc\thttps://example.com/article\n\n").unwrap();
+        let table = load_short_url_table(filepath.to_str().unwrap());
+        std::fs::remove_file(filepath).unwrap();
+        assert_eq!(table.resolve("https://bit.ly/abc/"), Some("https://example.com/article"));
+        assert_eq!(table.resolve("https://bit.ly/ABC"), None);
+        assert_eq!(table.resolve("https://example.com/abc"), None);
+
+        let keyer = UrlKeyer {
+            canonicalizer: UrlCanonicalizer::all(),
+            short_urls: table,
+            ..UrlKeyer::default()
+        };
+        assert_eq!(keyer.url_key("http://bit.ly/abc"), keyer.url_key("https://www.example.com/article/"));
+
+        let urls = ["http://bit.ly/abc", "http://t.co/x", "https://t.co/x", "http://goo.gl/y", "https://example.com"];
+        let posts: Vec<RedditPost> = urls
+            .iter()
+            .enumerate()
+            .map(|(i, url)| test_post(&i.to_string(), "news", url, 0))
+            .collect();
+        let unresolved = get_unresolved_short_urls(posts.into_iter(), &keyer, 10);
+        assert_eq!(unresolved, vec![("https://t.co/x".to_string(), 2), ("https://goo.gl/y".to_string(), 1)]);
+    }
+}
@@ -1,50 +1,366 @@
 //! Function simplifying the datasets by removing posts from the dataset,
 //! and removing fields
 
+use crate::dataset_format::{DatasetFormat, RecordWriter};
+use crate::deserializers;
+use crate::filters::PostFilter;
+use crate::read_files::{create_file, open_file};
+use crate::reddit_comment::*;
 use crate::reddit_post::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
 
-/// Simplify a post dataset by removing the unused fields
-/// Write the new dataset in a new file
-#[allow(dead_code)]
-pub fn simplify_post_dataset(filepath: &str, new_filepath: &str, keep_non_url_posts: bool) {
-    let file = File::open(filepath).unwrap();
-    let reader = BufReader::new(file);
-    let mut writer = csv::Writer::from_path(new_filepath).unwrap();
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let post = serde_json::from_str::<RedditPostJSON>(&line);
-        if post.is_err() {
-            println!("Reddit Post JSON: {}", line);
-            println!("Error while parsing JSON: {}", post.unwrap_err());
-            panic!();
+/// The set of columns written in a simplified post dataset
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColumnSet {
+    /// The columns of `LeanRedditPost`
+    Lean,
+    /// All the columns of `RedditPost`
+    Rich,
+}
+
+impl ColumnSet {
+    /// Get the record of a post written in a dataset of the given format.
+    /// The binary records are always full `RedditPost`s, since they are not self-describing,
+    /// with the fields that are not in the lean columns cleared.
+    pub fn post_record(self, post: RedditPost, format: DatasetFormat) -> PostRecord {
+        match (self, format) {
+            (ColumnSet::Rich, _) => PostRecord::Rich(post),
+            (ColumnSet::Lean, DatasetFormat::Binary) => PostRecord::Rich(RedditPost::from(post.into_lean())),
+            (ColumnSet::Lean, _) => PostRecord::Lean(post.into_lean()),
         }
-        let post = post.unwrap();
-        if let Some(post) = post.into_reddit_post() {
-            if !keep_non_url_posts && post.get_linked_url().is_none() {
-                continue;
+    }
+}
+
+/// The kind of objects stored in a pushshift dump
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DatasetKind {
+    Submissions,
+    Comments,
+}
+
+impl DatasetKind {
+    /// Guess the kind of dataset from the pushshift file name.
+    /// Comment dumps are named `RC_<date>`, and submission dumps `RS_<date>`.
+    pub fn from_filepath(filepath: &str) -> DatasetKind {
+        let filename = Path::new(filepath)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if filename.starts_with("RC_") {
+            DatasetKind::Comments
+        } else {
+            DatasetKind::Submissions
+        }
+    }
+}
+
+/// What to do with the lines of a dataset that cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop the simplification on the first invalid line
+    Fail,
+    /// Ignore the invalid lines
+    Skip,
+    /// Write the invalid lines in the given file, with their line number and the parsing error
+    Quarantine(String),
+}
+
+/// Summary of a dataset simplification
+#[derive(Clone, Debug, Default)]
+pub struct SimplifySummary {
+    pub n_lines: u64,
+    pub n_written: u64,
+    pub n_rejected: u64,
+}
+
+/// A line that could not be parsed, as written in the quarantine file
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RejectedLine<'a> {
+    pub line_number: u64,
+    #[serde(borrow)]
+    pub error: Cow<'a, str>,
+    #[serde(borrow)]
+    pub line: Cow<'a, str>,
+}
+
+/// Apply an error policy on the lines that cannot be parsed
+struct LineRejecter {
+    fail: bool,
+    quarantine: Option<BufWriter<File>>,
+}
+
+impl LineRejecter {
+    fn new(error_policy: &ErrorPolicy) -> Self {
+        let quarantine = match error_policy {
+            ErrorPolicy::Quarantine(filepath) => Some(BufWriter::new(File::create(filepath).unwrap())),
+            _ => None,
+        };
+        LineRejecter {
+            fail: *error_policy == ErrorPolicy::Fail,
+            quarantine,
+        }
+    }
+
+    /// Reject a line, given its line number (starting from 1) and the parsing error.
+    /// Returns an error if the simplification should stop.
+    fn reject(&mut self, line_number: u64, line: &[u8], error: &str) -> Result<(), String> {
+        let line = String::from_utf8_lossy(line);
+        if self.fail {
+            eprintln!("Reddit JSON at line {}: {}", line_number, line);
+            eprintln!("Error while parsing JSON: {}", error);
+            return Err(format!("Error: invalid JSON at line {}", line_number));
+        }
+        if let Some(quarantine) = &mut self.quarantine {
+            let rejected_line = RejectedLine {
+                line_number,
+                error: Cow::Borrowed(error),
+                line,
+            };
+            serde_json::to_writer(&mut *quarantine, &rejected_line).unwrap();
+            quarantine.write_all(b"\n").unwrap();
+        }
+        Ok(())
+    }
+}
+
+/// Options of the dataset simplifications
+#[derive(Debug, Clone)]
+pub struct SimplifyOptions {
+    pub error_policy: ErrorPolicy,
+    /// Number of threads parsing the JSON lines.
+    /// With a single thread, the lines are parsed by the thread writing the output.
+    pub n_threads: usize,
+    pub format: DatasetFormat,
+}
+
+/// Number of lines sent at once to a parsing thread
+const BATCH_SIZE: usize = 1024;
+
+/// A batch of lines sent to a parsing thread, with its index and the number of its first line
+type LinesBatch = (usize, u64, Vec<Vec<u8>>);
+
+/// A batch of parsed lines, with its index and the number of its first line.
+/// Each parsed line is kept along the raw line if it could not be parsed.
+type ParsedBatch<T> = (usize, u64, Vec<(Vec<u8>, Result<Option<T>, String>)>);
+
+/// Parse a line of a dataset with `parse`. Empty lines are ignored.
+fn parse_line<T, F>(line: &mut Vec<u8>, parse: &F) -> Result<Option<T>, String>
+where
+    F: Fn(&str) -> serde_json::Result<Option<T>>,
+{
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    if line.is_empty() {
+        return Ok(None);
+    }
+    std::str::from_utf8(line)
+        .map_err(|error| error.to_string())
+        .and_then(|line| parse(line).map_err(|error| error.to_string()))
+}
+
+/// Write the parsed lines of a dataset in a new dataset
+struct DatasetWriter<W: Write> {
+    writer: RecordWriter<W>,
+    rejecter: LineRejecter,
+    summary: SimplifySummary,
+}
+
+impl<W: Write> DatasetWriter<W> {
+    /// Write a parsed line, or reject it if it could not be parsed.
+    /// The lines should be written in order.
+    /// Returns an error if the simplification should stop.
+    fn write<T: Serialize>(&mut self, line: &[u8], parsed: Result<Option<T>, String>) -> Result<(), String> {
+        self.summary.n_lines += 1;
+        match parsed {
+            Ok(Some(item)) => {
+                self.writer.write(&item);
+                self.summary.n_written += 1;
+            }
+            Ok(None) => (),
+            Err(error) => {
+                self.rejecter.reject(self.summary.n_lines, line, &error)?;
+                self.summary.n_rejected += 1;
             }
-            writer.serialize(post).unwrap();
         }
+        Ok(())
     }
 }
 
+/// Simplify a dataset, by parsing each line with `parse`, and writing the results in a new dataset.
+/// `parse` returns None for the objects that should be removed from the dataset.
+fn simplify_dataset<T, F>(
+    filepath: &str,
+    new_filepath: &str,
+    options: &SimplifyOptions,
+    parse: F,
+) -> SimplifySummary
+where
+    T: Serialize + Send,
+    F: Fn(&str) -> serde_json::Result<Option<T>> + Sync,
+{
+    let reader = open_file(filepath);
+    let mut writer = DatasetWriter {
+        writer: RecordWriter::new(create_file(new_filepath), options.format),
+        rejecter: LineRejecter::new(&options.error_policy),
+        summary: SimplifySummary::default(),
+    };
+    let result = if options.n_threads <= 1 {
+        reader.split(b'\n').try_for_each(|line| {
+            let mut line = line.unwrap();
+            let parsed = parse_line(&mut line, &parse);
+            writer.write(&line, parsed)
+        })
+    } else {
+        simplify_dataset_par(reader, &mut writer, options.n_threads, &parse)
+    };
+    writer.writer.flush();
+    if let Err(error) = result {
+        panic!("{}", error);
+    }
+    writer.summary
+}
+
+/// Parse the lines of the reader with multiple threads, and write them in order.
+/// The calling thread writes the parsed lines, a thread reads the lines,
+/// and `n_threads` threads parse them.
+/// When the writer stops on an error, the channels are closed so that the other threads stop too.
+fn simplify_dataset_par<T, F, W>(
+    reader: Box<dyn BufRead + Send>,
+    writer: &mut DatasetWriter<W>,
+    n_threads: usize,
+    parse: &F,
+) -> Result<(), String>
+where
+    T: Serialize + Send,
+    F: Fn(&str) -> serde_json::Result<Option<T>> + Sync,
+    W: Write,
+{
+    std::thread::scope(|scope| {
+        let (lines_sender, lines_receiver) = mpsc::sync_channel::<LinesBatch>(2 * n_threads);
+        let lines_receiver = Arc::new(Mutex::new(lines_receiver));
+        let (parsed_sender, parsed_receiver) = mpsc::sync_channel::<ParsedBatch<T>>(2 * n_threads);
+        scope.spawn(move || {
+            let mut lines = reader.split(b'\n');
+            let mut line_number = 1;
+            for batch_index in 0.. {
+                let batch: Vec<_> = lines.by_ref().take(BATCH_SIZE).map(Result::unwrap).collect();
+                if batch.is_empty() {
+                    break;
+                }
+                let first_line = line_number;
+                line_number += batch.len() as u64;
+                if lines_sender.send((batch_index, first_line, batch)).is_err() {
+                    break;
+                }
+            }
+        });
+        for _ in 0..n_threads {
+            let lines_receiver = Arc::clone(&lines_receiver);
+            let parsed_sender = parsed_sender.clone();
+            scope.spawn(move || loop {
+                let batch = lines_receiver.lock().unwrap().recv();
+                let (batch_index, first_line, lines) = match batch {
+                    Ok(batch) => batch,
+                    Err(_) => break,
+                };
+                let parsed = lines
+                    .into_iter()
+                    .map(|mut line| {
+                        let parsed = parse_line(&mut line, parse);
+                        if parsed.is_ok() {
+                            line.clear();
+                        }
+                        (line, parsed)
+                    })
+                    .collect();
+                if parsed_sender.send((batch_index, first_line, parsed)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(lines_receiver);
+        drop(parsed_sender);
+
+        // The batches are received out of order, and are kept until all previous batches are written
+        let mut pending_batches = HashMap::new();
+        let mut next_batch_index = 0;
+        for (batch_index, first_line, parsed) in parsed_receiver.iter() {
+            pending_batches.insert(batch_index, (first_line, parsed));
+            while let Some((first_line, parsed)) = pending_batches.remove(&next_batch_index) {
+                debug_assert_eq!(first_line, writer.summary.n_lines + 1);
+                for (line, parsed) in parsed {
+                    writer.write(&line, parsed)?;
+                }
+                next_batch_index += 1;
+            }
+        }
+        assert!(pending_batches.is_empty());
+        Ok(())
+    })
+}
+
+/// Simplify a post dataset by removing the unused fields,
+/// and the posts that are not kept by the filter
+/// Write the new dataset in a new file
+pub fn simplify_post_dataset(
+    filepath: &str,
+    new_filepath: &str,
+    keep_non_url_posts: bool,
+    filter: &PostFilter,
+    columns: ColumnSet,
+    options: &SimplifyOptions,
+) -> SimplifySummary {
+    let parse = |line: &str| {
+        let post = serde_json::from_str::<RedditPostJSON>(line)?.into_reddit_post();
+        Ok(post.filter(|post| {
+            (keep_non_url_posts || post.get_linked_url().is_some()) && filter.keep_post(post)
+        }))
+    };
+    simplify_dataset(filepath, new_filepath, options, |line| {
+        Ok(parse(line)?.map(|post| columns.post_record(post, options.format)))
+    })
+}
+
+/// Simplify a comment dataset by removing the unused fields,
+/// and the comments that are not kept by the filter
+/// Write the new dataset in a new file
+pub fn simplify_comment_dataset(
+    filepath: &str,
+    new_filepath: &str,
+    filter: &PostFilter,
+    options: &SimplifyOptions,
+) -> SimplifySummary {
+    simplify_dataset(filepath, new_filepath, options, |line| {
+        let comment = serde_json::from_str::<RedditCommentJSON>(line)?.into_reddit_comment();
+        Ok(comment.filter(|comment| filter.keep_comment(comment)))
+    })
+}
+
 /// A struct representing a typed json reddit post.
 /// It contains a subset of the fields used by the reddit posts.
+/// The numeric fields are coerced from the different types they had across the dumps,
+/// and take a default value when they are missing, except the creation date which is required.
 #[derive(Deserialize, Debug, Clone)]
 struct RedditPostJSON<'a> {
     #[serde(borrow)]
     pub href_url: Option<Cow<'a, str>>,
-    pub num_comments: i32,
+    #[serde(default, deserialize_with = "deserializers::integer")]
+    pub num_comments: u64,
     #[serde(borrow)]
     pub promoted_url: Option<Cow<'a, str>>,
+    #[serde(default, deserialize_with = "deserializers::integer")]
     pub score: i32,
     pub hidden: Option<bool>,
-    pub gilded: i32,
+    #[serde(default, deserialize_with = "deserializers::integer")]
+    pub gilded: u64,
     #[serde(borrow)]
     pub subreddit: Option<Cow<'a, str>>,
     pub id: &'a str,
@@ -57,15 +373,26 @@ struct RedditPostJSON<'a> {
     #[serde(borrow)]
     pub permalink: Cow<'a, str>,
     pub author: &'a str,
-    pub created_utc: i32,
+    #[serde(deserialize_with = "deserializers::required_integer")]
+    pub created_utc: i64,
+    #[serde(borrow)]
+    pub domain: Option<Cow<'a, str>>,
+    pub is_self: Option<bool>,
+    #[serde(borrow)]
+    pub selftext: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub crosspost_parent: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub link_flair_text: Option<Cow<'a, str>>,
+    #[serde(default, deserialize_with = "deserializers::optional_integer")]
+    pub num_crossposts: Option<u64>,
+    #[serde(default, deserialize_with = "deserializers::integer")]
+    pub retrieved_on: i64,
 }
 
 /// to_string mapped on an option
 fn cow_to_opt_string<'a>(opt: Option<Cow<'a, str>>) -> Option<String> {
-    match opt {
-        None => None,
-        Some(s) => Some(s.to_string()),
-    }
+    opt.map(|s| s.to_string())
 }
 
 impl<'a> RedditPostJSON<'a> {
@@ -73,10 +400,8 @@ impl<'a> RedditPostJSON<'a> {
     /// The difference between the two is that we don't want to keep some
     /// Reddit Posts (like the promoted one)
     pub fn into_reddit_post(self) -> Option<RedditPost> {
-        if self.subreddit.is_none()
-            || self.promoted_url.is_some()
-            || (self.hidden.is_some() && self.hidden.unwrap())
-        {
+        let subreddit = self.subreddit?;
+        if self.promoted_url.is_some() || self.hidden == Some(true) {
             None
         } else {
             Some(RedditPost {
@@ -84,7 +409,7 @@ impl<'a> RedditPostJSON<'a> {
                 num_comments: self.num_comments,
                 score: self.score,
                 gilded: self.gilded,
-                subreddit: self.subreddit.unwrap().to_string(),
+                subreddit: subreddit.to_string(),
                 id: self.id.to_string(),
                 title: self.title.to_string(),
                 url: self.url.to_string(),
@@ -93,7 +418,205 @@ impl<'a> RedditPostJSON<'a> {
                 permalink: self.permalink.to_string(),
                 author: self.author.to_string(),
                 created_utc: self.created_utc,
+                domain: cow_to_opt_string(self.domain),
+                is_self: self.is_self,
+                selftext: cow_to_opt_string(self.selftext),
+                crosspost_parent: cow_to_opt_string(self.crosspost_parent),
+                link_flair_text: cow_to_opt_string(self.link_flair_text),
+                num_crossposts: self.num_crossposts,
+                retrieved_on: Some(self.retrieved_on).filter(|t| *t != 0),
             })
         }
     }
 }
+
+/// A struct representing a typed json reddit comment.
+/// It contains a subset of the fields used by the reddit comments.
+/// The numeric fields are coerced like in `RedditPostJSON`.
+#[derive(Deserialize, Debug, Clone)]
+struct RedditCommentJSON<'a> {
+    pub id: &'a str,
+    pub link_id: &'a str,
+    pub parent_id: &'a str,
+    #[serde(borrow)]
+    pub subreddit: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub author: Cow<'a, str>,
+    #[serde(default, deserialize_with = "deserializers::integer")]
+    pub score: i32,
+    #[serde(deserialize_with = "deserializers::required_integer")]
+    pub created_utc: i64,
+}
+
+impl<'a> RedditCommentJSON<'a> {
+    /// Transform the JSON Reddit Comment into a Reddit Comment
+    /// Comments that are not attached to a subreddit are removed
+    pub fn into_reddit_comment(self) -> Option<RedditComment> {
+        Some(RedditComment {
+            id: self.id.to_string(),
+            link_id: self.link_id.to_string(),
+            parent_id: self.parent_id.to_string(),
+            subreddit: self.subreddit?.to_string(),
+            author: self.author.to_string(),
+            score: self.score,
+            created_utc: self.created_utc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Try to deserialize a comment sampled from the dataset, and to join it to its post.
+    #[test]
+    fn deserialize_comment() {
+        let data = r#"{"author_flair_text":null,"archived":true,"score":3,"retrieved_on":1425124228,"edited":false,"id":"c0299an","subreddit":"reddit.com","author":"jpeddie","ups":3,"downs":0,"body":"Thanks","subreddit_id":"t5_6","parent_id":"t1_c02999p","link_id":"t3_6jyfb","name":"t1_c0299an","controversiality":0,"author_flair_css_class":null,"score_hidden":false,"distinguished":null,"created_utc":"1213387512","gilded":0}"#;
+        let comment = serde_json::from_str::<RedditCommentJSON>(data).unwrap();
+        let comment = comment.into_reddit_comment().unwrap();
+        assert_eq!(comment.get_submission_id(), "6jyfb");
+        assert_eq!(comment.created_utc, 1213387512);
+        assert_eq!(DatasetKind::from_filepath("dumps/RC_2008-06.bz2"), DatasetKind::Comments);
+    }
+
+    /// Simplify a dataset containing invalid lines, and check they are written in the quarantine file.
+    #[test]
+    fn quarantine_invalid_lines() {
+        let directory = std::env::temp_dir().join("reddit_reposts_quarantine_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("RC_test");
+        let output = directory.join("RC_test_CSV");
+        let quarantine = directory.join("RC_test_CSV_rejected");
+        let valid = r#"{"id":"c1","link_id":"t3_a","parent_id":"t3_a","subreddit":"pics","author":"u","score":1,"created_utc":1}"#;
+        std::fs::write(&input, format!("{}\n{{\"id\":\n{}\n", valid, valid)).unwrap();
+
+        let options = SimplifyOptions {
+            error_policy: ErrorPolicy::Quarantine(quarantine.to_str().unwrap().to_string()),
+            n_threads: 1,
+            format: DatasetFormat::Csv,
+        };
+        let summary =
+            simplify_comment_dataset(input.to_str().unwrap(), output.to_str().unwrap(), &PostFilter::default(), &options);
+        assert_eq!(summary.n_lines, 3);
+        assert_eq!(summary.n_written, 2);
+        assert_eq!(summary.n_rejected, 1);
+
+        let quarantine = std::fs::read_to_string(quarantine).unwrap();
+        let rejected: RejectedLine = serde_json::from_str(quarantine.trim()).unwrap();
+        assert_eq!(rejected.line_number, 2);
+        assert_eq!(rejected.line, "{\"id\":");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// Check that the multi-threaded simplification keeps the same output as the single-threaded one.
+    #[test]
+    fn simplify_multi_threaded() {
+        let directory = std::env::temp_dir().join("reddit_reposts_threads_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("RC_test");
+        let mut lines = String::new();
+        for i in 0..3 * BATCH_SIZE + 17 {
+            if i % 1000 == 999 {
+                lines += "invalid\n";
+            } else {
+                lines += &format!(
+                    r#"{{"id":"c{}","link_id":"t3_a","parent_id":"t3_a","subreddit":"pics","author":"u","score":1,"created_utc":{}}}"#,
+                    i, i
+                );
+                lines += "\n";
+            }
+        }
+        std::fs::write(&input, lines).unwrap();
+
+        let mut outputs = vec![];
+        for &n_threads in [1, 4].iter() {
+            let output = directory.join(format!("RC_test_CSV_{}", n_threads));
+            let options = SimplifyOptions {
+                error_policy: ErrorPolicy::Skip,
+                n_threads,
+                format: DatasetFormat::Csv,
+            };
+            let summary =
+                simplify_comment_dataset(input.to_str().unwrap(), output.to_str().unwrap(), &PostFilter::default(), &options);
+            assert_eq!(summary.n_lines, 3 * BATCH_SIZE as u64 + 17);
+            assert_eq!(summary.n_rejected, 3);
+            outputs.push(std::fs::read(output).unwrap());
+        }
+        assert_eq!(outputs[0], outputs[1]);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// Check that the multi-threaded simplification stops on an invalid line with the fail policy,
+    /// instead of waiting for the threads still reading and parsing the lines.
+    #[test]
+    fn fail_multi_threaded() {
+        let directory = std::env::temp_dir().join("reddit_reposts_fail_threads_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("RC_test");
+        let output = directory.join("RC_test_CSV");
+        let valid = r#"{"id":"c1","link_id":"t3_a","parent_id":"t3_a","subreddit":"pics","author":"u","score":1,"created_utc":1}"#;
+        let mut lines = String::from("invalid\n");
+        for _ in 0..64 * BATCH_SIZE {
+            lines += valid;
+            lines += "\n";
+        }
+        std::fs::write(&input, lines).unwrap();
+
+        let options = SimplifyOptions {
+            error_policy: ErrorPolicy::Fail,
+            n_threads: 4,
+            format: DatasetFormat::Csv,
+        };
+        let result = std::panic::catch_unwind(|| {
+            simplify_comment_dataset(input.to_str().unwrap(), output.to_str().unwrap(), &PostFilter::default(), &options)
+        });
+        assert!(result.is_err());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// Submissions sampled from each era of the dump format
+    const SUBMISSION_ERAS: [(&str, &str); 4] = [
+        // Early dumps, before the gilding was introduced in late 2012
+        (
+            "no gilded field",
+            r#"{"id":"dcu4","subreddit":"reddit.com","title":"Reddit is now open source","url":"http://code.reddit.com/","permalink":"/r/reddit.com/comments/dcu4/reddit_is_now_open_source/","author":"spez","created_utc":1213387512,"num_comments":12,"score":"47","over_18":false,"domain":"code.reddit.com","retrieved_on":1397198345}"#,
+        ),
+        // Dumps storing the timestamps as strings
+        (
+            "string created_utc",
+            r#"{"id":"1fxp0a","subreddit":"pics","title":"My cat","url":"http://i.imgur.com/aaaaaaa.jpg","permalink":"/r/pics/comments/1fxp0a/my_cat/","author":"someone","created_utc":"1370000000","num_comments":"3","score":10,"gilded":0,"over_18":false,"edited":false,"retrieved_on":1413287000}"#,
+        ),
+        // Dumps storing the timestamps as floats
+        (
+            "float created_utc",
+            r#"{"id":"7gtkfb","subreddit":"videos","title":"A video","url":"https://www.youtube.com/watch?v=aaaaaaaaaaa","permalink":"/r/videos/comments/7gtkfb/a_video/","author":"someone","created_utc":1512086400.0,"num_comments":0,"score":1,"gilded":0,"over_18":false,"retrieved_on":1514000000.0}"#,
+        ),
+        // Recent dumps, where the removed posts may miss their counters
+        (
+            "missing counters",
+            r#"{"id":"aaaaaa","subreddit":"news","title":"News","url":"https://example.com/news","permalink":"/r/news/comments/aaaaaa/news/","author":"[deleted]","created_utc":1546300800,"num_comments":null,"over_18":false,"gildings":{},"removed_by_category":"moderator"}"#,
+        ),
+    ];
+
+    /// Parse a submission from each era of the dump format.
+    #[test]
+    fn deserialize_submission_eras() {
+        let expected = [
+            (12, 47, 0, 1213387512),
+            (3, 10, 0, 1370000000),
+            (0, 1, 0, 1512086400),
+            (0, 0, 0, 1546300800),
+        ];
+        for ((era, data), expected) in SUBMISSION_ERAS.iter().zip(expected.iter()) {
+            let post = serde_json::from_str::<RedditPostJSON>(data)
+                .unwrap_or_else(|e| panic!("Cannot parse submission with {}: {}", era, e))
+                .into_reddit_post()
+                .unwrap();
+            let parsed = (post.num_comments, post.score, post.gilded, post.created_utc);
+            assert_eq!(parsed, *expected, "Wrong fields for submission with {}", era);
+        }
+        // A submission without creation date is rejected, rather than dated to 1970
+        let undated = SUBMISSION_ERAS[0].1.replace(r#""created_utc":1213387512,"#, "");
+        assert!(serde_json::from_str::<RedditPostJSON>(&undated).is_err());
+    }
+}
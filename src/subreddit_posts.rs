@@ -1,15 +1,17 @@
 //! Functions to get and save posts from chosen subreddits
 
 pub use crate::reddit_post::RedditPost;
+use crate::url_keys::UrlKeyer;
 pub use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
 
-/// Get all posts from chosen subreddits
+/// Get all posts from chosen subreddits, grouped by the keys of the urls they link to
 #[allow(dead_code)]
 pub fn get_subreddit_posts<IT>(
     iterator: IT,
     subreddit_names: HashSet<String>,
+    keyer: &UrlKeyer,
 ) -> HashMap<String, HashMap<String, HashSet<RedditPost>>>
 where
     IT: Iterator<Item = RedditPost>,
@@ -19,14 +21,13 @@ where
         .map(|name| (name, HashMap::new()))
         .collect();
     for post in iterator {
-        let url = post.get_linked_url();
-        if let Some(url) = url {
-            let subreddit_map = map.get_mut(&post.subreddit);
-            if let Some(subreddit_map) = subreddit_map {
-                if !subreddit_map.contains_key(&url) {
-                    subreddit_map.insert(url.to_string(), HashSet::new());
-                }
-                subreddit_map.get_mut(&url).unwrap().insert(post);
+        let subreddit_map = map.get_mut(&post.subreddit);
+        if let Some(subreddit_map) = subreddit_map {
+            for url in keyer.post_keys(&post) {
+                subreddit_map
+                    .entry(url)
+                    .or_insert_with(HashSet::new)
+                    .insert(post.clone());
             }
         }
     }
@@ -38,7 +39,7 @@ where
 pub fn save_subreddit_posts(posts: HashMap<String, HashSet<RedditPost>>, filepath: String) {
     let posts = serde_json::to_string(&posts).unwrap();
     let mut file = File::create(filepath).unwrap();
-    file.write(posts.as_bytes()).unwrap();
+    file.write_all(posts.as_bytes()).unwrap();
 }
 
 /// Load the subreddits posts from a file
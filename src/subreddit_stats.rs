@@ -1,57 +1,100 @@
+use crate::read_files::create_file;
 use crate::reddit_post::RedditPost;
+use crate::url_keys::UrlKeyer;
 use serde::{Deserialize, Serialize};
 /// Functions used to compute subreddits statistics
-use std::collections::HashMap;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufReader;
 
 /// Structure containing the interesting stats about a subreddit
 #[derive(Deserialize, Serialize, Copy, Clone, Debug)]
 pub struct SubredditStats {
-    pub n_posts: i32,
-    pub n_comments: i32,
+    pub n_posts: u64,
+    pub n_comments: u64,
     pub sum_score: i64,
-    pub n_posts_over_18: i32,
+    pub n_posts_over_18: u64,
+    /// Number of distinct urls, media or domains linked by the posts, depending on the granularity.
+    /// It is 0 when the keys are not counted, and in the stats files computed before it was added.
+    #[serde(default)]
+    pub n_distinct_keys: u64,
 }
 
+/// Hashes of the keys linked by the posts of each subreddit
+type SubredditsKeys = HashMap<String, HashSet<u64>>;
+
 /// Get the all the present subreddits.
 /// Get only the n_subreddits subreddit with the most posts if given
 #[allow(dead_code)]
-pub fn compute_subreddits_stats_par<IT>(iterators: Vec<IT>) -> HashMap<String, SubredditStats>
+pub fn compute_subreddits_stats_par<IT>(iterators: Vec<IT>, keyer: Option<&UrlKeyer>) -> HashMap<String, SubredditStats>
 where
     IT: Iterator<Item = RedditPost> + Send + 'static,
 {
     let threads: Vec<_> = iterators
         .into_iter()
-        .map(|it| std::thread::spawn(move || compute_subreddits_stats(it)))
+        .map(|it| {
+            let keyer = keyer.cloned();
+            std::thread::spawn(move || compute_subreddits_stats_and_keys(it, keyer.as_ref()))
+        })
         .collect();
     let mut stats = HashMap::new();
+    let mut keys = SubredditsKeys::new();
     for thread in threads {
-        let stats_ = thread.join().unwrap();
+        let (stats_, keys_) = thread.join().unwrap();
         for (subreddit, stat) in stats_ {
-            if !stats.contains_key(&subreddit) {
-                stats.insert(subreddit, stat);
-            } else {
-                let stat_subreddit = stats.get_mut(&subreddit).unwrap();
-                stat_subreddit.n_posts += stat.n_posts;
-                stat_subreddit.n_comments += stat.n_comments;
-                stat_subreddit.sum_score += stat.sum_score;
-                stat_subreddit.n_posts_over_18 += stat.n_posts_over_18;
+            match stats.entry(subreddit) {
+                Entry::Vacant(entry) => {
+                    entry.insert(stat);
+                }
+                Entry::Occupied(mut entry) => {
+                    let stat_subreddit = entry.get_mut();
+                    stat_subreddit.n_posts += stat.n_posts;
+                    stat_subreddit.n_comments += stat.n_comments;
+                    stat_subreddit.sum_score += stat.sum_score;
+                    stat_subreddit.n_posts_over_18 += stat.n_posts_over_18;
+                }
             }
         }
+        for (subreddit, subreddit_keys) in keys_ {
+            keys.entry(subreddit).or_default().extend(subreddit_keys);
+        }
     }
+    set_n_distinct_keys(&mut stats, keys);
     stats
 }
 
 /// Get the all the present subreddits.
-/// Get only the n_subreddits subreddit with the most posts if given
+/// Get only the n_subreddits subreddit with the most posts if given.
+/// The distinct keys linked by the subreddits are counted only if a keyer is given.
 #[allow(dead_code)]
-pub fn compute_subreddits_stats<IT>(iterator: IT) -> HashMap<String, SubredditStats>
+pub fn compute_subreddits_stats<IT>(iterator: IT, keyer: Option<&UrlKeyer>) -> HashMap<String, SubredditStats>
+where
+    IT: Iterator<Item = RedditPost>,
+{
+    let (mut stats, keys) = compute_subreddits_stats_and_keys(iterator, keyer);
+    set_n_distinct_keys(&mut stats, keys);
+    stats
+}
+
+/// Compute the stats of the subreddits, and the keys linked by their posts if a keyer is given
+fn compute_subreddits_stats_and_keys<IT>(
+    iterator: IT,
+    keyer: Option<&UrlKeyer>,
+) -> (HashMap<String, SubredditStats>, SubredditsKeys)
 where
     IT: Iterator<Item = RedditPost>,
 {
     let mut subreddits = HashMap::<String, SubredditStats>::new();
+    let mut keys = SubredditsKeys::new();
     for post in iterator {
+        let post_keys = keyer.map(|keyer| keyer.post_keys(&post)).unwrap_or_default();
+        if !post_keys.is_empty() {
+            let subreddit_keys = keys.entry(post.subreddit.clone()).or_default();
+            subreddit_keys.extend(post_keys.iter().map(|key| key_hash(key)));
+        }
         let n_posts_over_18 = if post.over_18 { 1 } else { 0 };
         if let Some(stats) = subreddits.get_mut(&post.subreddit) {
             stats.n_posts += 1;
@@ -66,26 +109,119 @@ where
                     n_comments: post.num_comments,
                     sum_score: post.score as i64,
                     n_posts_over_18,
+                    n_distinct_keys: 0,
                 },
             );
         }
     }
-    subreddits
+    (subreddits, keys)
+}
+
+/// Hash a key, to count the distinct keys without storing them
+fn key_hash(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Set the number of distinct keys of the subreddits
+fn set_n_distinct_keys(stats: &mut HashMap<String, SubredditStats>, keys: SubredditsKeys) {
+    for (subreddit, subreddit_keys) in keys {
+        if let Some(stats) = stats.get_mut(&subreddit) {
+            stats.n_distinct_keys = subreddit_keys.len() as u64;
+        }
+    }
+}
+
+/// Version of the stats file format.
+/// The first version was a map from the subreddits to their stats, with 32 bits counters.
+const STATS_FILE_VERSION: u32 = 2;
+
+/// The content of a stats file
+#[derive(Deserialize, Serialize)]
+struct StatsFile {
+    version: u32,
+    subreddits: HashMap<String, SubredditStats>,
+}
+
+/// Stats of a subreddit, as written in the first version of the stats file
+#[derive(Deserialize, Copy, Clone, Debug)]
+struct SubredditStatsV1 {
+    n_posts: i32,
+    n_comments: i32,
+    sum_score: i64,
+    n_posts_over_18: i32,
+}
+
+impl From<SubredditStatsV1> for SubredditStats {
+    /// Counters that overflowed are negative in the first version.
+    /// They are read back as wrapped 32 bits unsigned counters,
+    /// which is exact as long as they did not exceed 2^32.
+    fn from(stats: SubredditStatsV1) -> Self {
+        SubredditStats {
+            n_posts: stats.n_posts as u32 as u64,
+            n_comments: stats.n_comments as u32 as u64,
+            sum_score: stats.sum_score,
+            n_posts_over_18: stats.n_posts_over_18 as u32 as u64,
+            n_distinct_keys: 0,
+        }
+    }
 }
 
-/// Save the subreddits stats in a file
+/// Any version of the stats file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyStatsFile {
+    Current(StatsFile),
+    V1(HashMap<String, SubredditStatsV1>),
+}
+
+/// Save the subreddits stats in a file, or in the standard output if the path is `-`
 #[allow(dead_code)]
 pub fn save_subreddits_stats(stats: &HashMap<String, SubredditStats>, filepath: &str) {
+    let stats = StatsFile {
+        version: STATS_FILE_VERSION,
+        subreddits: stats.clone(),
+    };
     let stats = serde_json::to_string(&stats).unwrap();
-    let mut file = File::create(filepath).unwrap();
-    file.write(stats.as_bytes()).unwrap();
+    let mut file = create_file(filepath);
+    file.write_all(stats.as_bytes()).unwrap();
 }
 
-/// Load the subreddits stats from a file
+/// Load the subreddits stats from a file.
+/// Files written by previous versions are converted to the current format.
 #[allow(dead_code)]
 pub fn load_subreddits_stats(filepath: &str) -> HashMap<String, SubredditStats> {
-    let file = File::open(filepath).unwrap();
-    serde_json::from_reader(file).unwrap()
+    let file = BufReader::new(File::open(filepath).unwrap());
+    match serde_json::from_reader(file).unwrap() {
+        AnyStatsFile::Current(stats) => {
+            assert_eq!(
+                stats.version, STATS_FILE_VERSION,
+                "Unsupported stats file version"
+            );
+            stats.subreddits
+        }
+        AnyStatsFile::V1(stats) => {
+            let n_overflows = stats
+                .values()
+                .filter(|s| s.n_posts < 0 || s.n_comments < 0 || s.n_posts_over_18 < 0)
+                .count();
+            if n_overflows > 0 {
+                println!(
+                    "Warning: {} subreddits have overflowed counters in {}, they should be recomputed",
+                    n_overflows, filepath
+                );
+            }
+            stats.into_iter().map(|(s, stats)| (s, stats.into())).collect()
+        }
+    }
+}
+
+/// Convert a stats file written by a previous version into the current format
+#[allow(dead_code)]
+pub fn migrate_subreddits_stats(filepath: &str, new_filepath: &str) {
+    let stats = load_subreddits_stats(filepath);
+    save_subreddits_stats(&stats, new_filepath);
 }
 
 /// Get the most popular subreddits according to the post statistics
@@ -95,6 +231,71 @@ pub fn get_most_popular_subreddits(
     stats: HashMap<String, SubredditStats>,
 ) -> HashMap<String, SubredditStats> {
     let mut stats_vec: Vec<_> = stats.into_iter().collect();
-    stats_vec.sort_by(|(_, stat1), (_, stat2)| stat2.sum_score.cmp(&stat1.sum_score));
+    stats_vec.sort_by_key(|(_, stat)| std::cmp::Reverse(stat.sum_score));
     stats_vec.into_iter().take(n_subreddits).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reddit_post::test_post;
+    use crate::url_canonicalization::UrlCanonicalizer;
+    use crate::url_keys::Granularity;
+
+    /// Count the distinct urls and domains linked by the posts of the subreddits
+    #[test]
+    fn count_distinct_keys() {
+        let urls = [
+            ("news", "http://www.bbc.co.uk/news/1"),
+            ("news", "https://bbc.co.uk/news/1/"),
+            ("news", "https://news.bbc.co.uk/news/2"),
+            ("news", "https://www.reddit.com/r/news/comments/s1/t/"),
+            ("pics", "https://i.imgur.com/AbCdEfG.jpg"),
+        ];
+        let posts: Vec<RedditPost> = urls
+            .iter()
+            .enumerate()
+            .map(|(i, (subreddit, url))| RedditPost {
+                permalink: "/r/news/comments/s1/t/".to_string(),
+                ..test_post(&i.to_string(), subreddit, url, 0)
+            })
+            .collect();
+
+        let mut keyer = UrlKeyer {
+            canonicalizer: UrlCanonicalizer::all(),
+            ..UrlKeyer::default()
+        };
+        let stats = compute_subreddits_stats(posts.clone().into_iter(), Some(&keyer));
+        assert_eq!(stats["news"].n_posts, 4);
+        assert_eq!(stats["news"].n_distinct_keys, 2);
+        assert_eq!(stats["pics"].n_distinct_keys, 1);
+        let stats = compute_subreddits_stats(posts.clone().into_iter(), None);
+        assert_eq!(stats["news"].n_distinct_keys, 0);
+
+        keyer.granularity = Granularity::Domain;
+        let mut first_posts = posts;
+        let last_posts = first_posts.split_off(2);
+        let stats = compute_subreddits_stats_par(vec![first_posts.into_iter(), last_posts.into_iter()], Some(&keyer));
+        assert_eq!(stats["news"].n_posts, 4);
+        assert_eq!(stats["news"].n_distinct_keys, 1);
+    }
+
+    /// Load a stats file written with 32 bits counters, one of them having overflowed.
+    #[test]
+    fn load_v1_stats_file() {
+        let filepath = std::env::temp_dir().join("reddit_reposts_v1_stats");
+        let data = r#"{"AskReddit":{"n_posts":1000,"n_comments":-1294967296,"sum_score":12345678901,"n_posts_over_18":3}}"#;
+        std::fs::write(&filepath, data).unwrap();
+        let stats = load_subreddits_stats(filepath.to_str().unwrap());
+        let stats = stats["AskReddit"];
+        assert_eq!(stats.n_posts, 1000);
+        assert_eq!(stats.n_comments, 3_000_000_000);
+        assert_eq!(stats.sum_score, 12345678901);
+
+        migrate_subreddits_stats(filepath.to_str().unwrap(), filepath.to_str().unwrap());
+        let migrated = std::fs::read_to_string(&filepath).unwrap();
+        assert!(migrated.starts_with(r#"{"version":2,"#));
+        assert_eq!(load_subreddits_stats(filepath.to_str().unwrap())["AskReddit"].n_comments, 3_000_000_000);
+        std::fs::remove_file(filepath).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! Detection of the posts with near-identical titles, with MinHash signatures
+//! and locality-sensitive hashing.
+//! It finds the reposts of rehosted content, that do not share their url with the original post.
+
+use crate::reddit_post::RedditPost;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+/// Number of characters of the shingles of the titles
+const SHINGLE_LENGTH: usize = 5;
+
+/// Buckets with more posts are only compared with their first post, to avoid a quadratic time
+/// on the very common titles
+const MAX_PAIRWISE_BUCKET: usize = 64;
+
+/// Parameters of the detection of similar titles
+#[derive(Debug, Clone)]
+pub struct TitleSimilarityOptions {
+    /// Number of bands of the locality-sensitive hashing
+    pub n_bands: usize,
+    /// Number of MinHash values in each band
+    pub n_rows: usize,
+    /// Minimal estimated Jaccard similarity of the shingles of two similar titles
+    pub threshold: f64,
+    /// Titles shorter than this, once normalized, are ignored
+    pub min_title_length: usize,
+}
+
+impl Default for TitleSimilarityOptions {
+    fn default() -> Self {
+        TitleSimilarityOptions {
+            n_bands: 16,
+            n_rows: 4,
+            threshold: 0.8,
+            min_title_length: 20,
+        }
+    }
+}
+
+/// A post of a cluster of similar titles
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterPost {
+    pub id: String,
+    pub subreddit: String,
+    pub created_utc: i64,
+    pub title: String,
+    /// Estimated similarity with the title of the first post of the cluster
+    pub similarity: f64,
+}
+
+/// Posts with similar titles, sorted by creation date
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TitleCluster {
+    pub cluster: usize,
+    pub posts: Vec<ClusterPost>,
+}
+
+/// Normalize a title, keeping only its lowercase words
+pub fn normalize_title(title: &str) -> String {
+    let title: String = title
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    title.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Get the hashes of the character shingles of a normalized title.
+/// The titles shorter than a shingle are a single shingle, and the empty titles have none.
+fn shingles(title: &str) -> HashSet<u64> {
+    let chars: Vec<_> = title.chars().collect();
+    if chars.is_empty() {
+        return HashSet::new();
+    }
+    chars
+        .windows(SHINGLE_LENGTH.min(chars.len()))
+        .map(|shingle| {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Computes the MinHash signatures of sets of hashes
+struct MinHasher {
+    coefficients: Vec<(u64, u64)>,
+}
+
+impl MinHasher {
+    /// Create the hash functions, with a fixed seed so that the signatures are reproducible
+    fn new(n_hashes: usize) -> Self {
+        let mut state: u64 = 0x5eed;
+        let mut next = || {
+            // splitmix64
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        let coefficients = (0..n_hashes).map(|_| (next() | 1, next())).collect();
+        MinHasher { coefficients }
+    }
+
+    fn signature(&self, hashes: &HashSet<u64>) -> Vec<u32> {
+        self.coefficients
+            .iter()
+            .map(|(a, b)| {
+                hashes
+                    .iter()
+                    .map(|x| (a.wrapping_mul(*x).wrapping_add(*b) >> 32) as u32)
+                    .min()
+                    .unwrap_or(u32::MAX)
+            })
+            .collect()
+    }
+}
+
+/// Estimate the Jaccard similarity of two sets from their signatures
+fn similarity(signature1: &[u32], signature2: &[u32]) -> f64 {
+    let n_equal = signature1.iter().zip(signature2).filter(|(h1, h2)| h1 == h2).count();
+    n_equal as f64 / signature1.len() as f64
+}
+
+/// Find the root of an element in a union-find forest
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Get the clusters of posts with similar titles, the largest clusters first
+pub fn get_title_clusters<IT>(iterator: IT, options: &TitleSimilarityOptions) -> Vec<TitleCluster>
+where
+    IT: Iterator<Item = RedditPost>,
+{
+    let min_hasher = MinHasher::new(options.n_bands * options.n_rows);
+    let mut posts = vec![];
+    let mut signatures = vec![];
+    for post in iterator {
+        let title = normalize_title(&post.title);
+        // The empty titles have no shingles, and would all be in the same cluster
+        if title.is_empty() || title.chars().count() < options.min_title_length {
+            continue;
+        }
+        signatures.push(min_hasher.signature(&shingles(&title)));
+        // Only the fields written in the clusters are kept
+        posts.push(ClusterPost {
+            id: post.id,
+            subreddit: post.subreddit,
+            created_utc: post.created_utc,
+            title: post.title,
+            similarity: 1.0,
+        });
+    }
+
+    let mut parents: Vec<_> = (0..posts.len()).collect();
+    for band in 0..options.n_bands {
+        let rows = band * options.n_rows..(band + 1) * options.n_rows;
+        let mut buckets = HashMap::<&[u32], Vec<usize>>::new();
+        for (i, signature) in signatures.iter().enumerate() {
+            buckets.entry(&signature[rows.clone()]).or_default().push(i);
+        }
+        for bucket in buckets.values().filter(|bucket| bucket.len() > 1) {
+            let n_compared = if bucket.len() > MAX_PAIRWISE_BUCKET { 1 } else { bucket.len() };
+            for (k, &i) in bucket.iter().enumerate().take(n_compared) {
+                for &j in &bucket[k + 1..] {
+                    if similarity(&signatures[i], &signatures[j]) >= options.threshold {
+                        let root_i = find(&mut parents, i);
+                        let root_j = find(&mut parents, j);
+                        parents[root_i] = root_j;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut members = HashMap::<usize, Vec<usize>>::new();
+    for i in 0..posts.len() {
+        let root = find(&mut parents, i);
+        members.entry(root).or_default().push(i);
+    }
+    let mut clusters: Vec<_> = members.into_values().filter(|members| members.len() > 1).collect();
+    clusters.sort_by_key(|members| (std::cmp::Reverse(members.len()), members[0]));
+    clusters
+        .into_iter()
+        .enumerate()
+        .map(|(cluster, mut members)| {
+            members.sort_by_key(|i| posts[*i].created_utc);
+            let first = members[0];
+            let posts = members
+                .into_iter()
+                .map(|i| ClusterPost {
+                    similarity: similarity(&signatures[first], &signatures[i]),
+                    ..posts[i].clone()
+                })
+                .collect();
+            TitleCluster { cluster, posts }
+        })
+        .collect()
+}
+
+/// Save the clusters in a file, with one JSON object per line
+pub fn save_title_clusters(clusters: &[TitleCluster], filepath: &str) {
+    let mut file = BufWriter::new(File::create(filepath).unwrap());
+    for cluster in clusters {
+        serde_json::to_writer(&mut file, cluster).unwrap();
+        file.write_all(b"\n").unwrap();
+    }
+}
+
+/// Load the clusters of a file written by `save_title_clusters`,
+/// as a map from the post ids to their cluster
+pub fn load_title_clusters(filepath: &str) -> HashMap<String, usize> {
+    let file = BufReader::new(File::open(filepath).unwrap());
+    let mut clusters = HashMap::new();
+    for line in file.lines() {
+        let cluster: TitleCluster = serde_json::from_str(&line.unwrap()).unwrap();
+        for post in cluster.posts {
+            clusters.insert(post.id, cluster.cluster);
+        }
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reddit_post::test_post;
+    use crate::url_keys::UrlKeyer;
+
+    /// Cluster the near-identical titles, and only them
+    #[test]
+    fn cluster_similar_titles() {
+        assert_eq!(normalize_title("  My cat, sleeping on the KEYBOARD! [OC]"), "my cat sleeping on the keyboard oc");
+        assert!(shingles(&normalize_title("?!")).is_empty());
+        assert_eq!(shingles("cat").len(), 1);
+
+        let titles = [
+            ("a", "pics", 3, "My cat sleeping on my keyboard again"),
+            ("b", "aww", 1, "My cat sleeping on my keyboard, again!"),
+            ("c", "funny", 2, "my cat sleeping on my keyboard again [OC]"),
+            ("d", "pics", 4, "A completely different picture of a sunset"),
+            ("e", "pics", 5, "Short"),
+            ("f", "news", 6, "The parliament votes the budget for next year"),
+        ];
+        let posts: Vec<RedditPost> = titles
+            .iter()
+            .map(|(id, subreddit, created_utc, title)| RedditPost {
+                title: title.to_string(),
+                ..test_post(id, subreddit, "", *created_utc)
+            })
+            .collect();
+        let clusters = get_title_clusters(posts.clone().into_iter(), &TitleSimilarityOptions::default());
+        assert_eq!(clusters.len(), 1);
+        let ids: Vec<_> = clusters[0].posts.iter().map(|post| post.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c", "a"]);
+        assert_eq!(clusters[0].posts[0].similarity, 1.0);
+        assert!(clusters[0].posts[1].similarity >= 0.8);
+
+        let filepath = std::env::temp_dir().join("reddit_reposts_title_clusters");
+        save_title_clusters(&clusters, filepath.to_str().unwrap());
+        let post_clusters = load_title_clusters(filepath.to_str().unwrap());
+        std::fs::remove_file(filepath).unwrap();
+        assert_eq!(post_clusters.len(), 3);
+        assert_eq!(post_clusters["a"], 0);
+
+        let keyer = UrlKeyer {
+            title_clusters: Some(post_clusters),
+            ..UrlKeyer::default()
+        };
+        assert_eq!(keyer.post_keys(&posts[0]), vec!["title:0".to_string()]);
+        assert!(keyer.post_keys(&posts[3]).is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! Canonicalization of the urls, so that the different spellings of an url share the same key
+
+/// Names of the canonicalization rules, as given in the command line
+pub const CANONICALIZATION_RULES: [&str; 7] = [
+    "scheme",
+    "host-case",
+    "www",
+    "trailing-slash",
+    "entities",
+    "fragment",
+    "tracking",
+];
+
+/// Query parameters used to track the clicks, that do not change the linked content.
+/// The parameters starting with `utm_` are also removed.
+const TRACKING_PARAMS: [&str; 4] = ["fbclid", "gclid", "igshid", "mc_eid"];
+
+/// Rewrite the urls into a canonical form.
+/// Each rule can be enabled separately, none of them are enabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct UrlCanonicalizer {
+    /// Use https instead of http
+    pub https: bool,
+    /// Write the scheme and the host in lowercase
+    pub lowercase_host: bool,
+    /// Remove the `www.` prefix of the host
+    pub strip_www: bool,
+    /// Remove the slashes at the end of the path
+    pub strip_trailing_slash: bool,
+    /// Decode the html entities, like the `&amp;` found in the urls of the dumps
+    pub decode_entities: bool,
+    /// Remove the fragment, after the `#`
+    pub strip_fragment: bool,
+    /// Remove the tracking query parameters, like `utm_source`
+    pub strip_tracking_params: bool,
+}
+
+impl UrlCanonicalizer {
+    /// Canonicalizer with every rule enabled
+    pub fn all() -> Self {
+        UrlCanonicalizer {
+            https: true,
+            lowercase_host: true,
+            strip_www: true,
+            strip_trailing_slash: true,
+            decode_entities: true,
+            strip_fragment: true,
+            strip_tracking_params: true,
+        }
+    }
+
+    /// Enable or disable a rule given by its name in `CANONICALIZATION_RULES`
+    pub fn set_rule(&mut self, rule: &str, enabled: bool) -> Result<(), String> {
+        let field = match rule {
+            "scheme" => &mut self.https,
+            "host-case" => &mut self.lowercase_host,
+            "www" => &mut self.strip_www,
+            "trailing-slash" => &mut self.strip_trailing_slash,
+            "entities" => &mut self.decode_entities,
+            "fragment" => &mut self.strip_fragment,
+            "tracking" => &mut self.strip_tracking_params,
+            _ => return Err(format!("Unknown canonicalization rule {}", rule)),
+        };
+        *field = enabled;
+        Ok(())
+    }
+
+    /// Get the canonical form of an url
+    pub fn canonicalize(&self, url: &str) -> String {
+        let decoded;
+        let url = if self.decode_entities {
+            decoded = decode_html_entities(url);
+            &decoded
+        } else {
+            url
+        };
+
+        let (url, fragment) = split_once(url, '#');
+        let (url, query) = split_once(url, '?');
+        let (scheme, url) = match url.find("://") {
+            Some(i) => (Some(&url[..i]), &url[i + 3..]),
+            None => (None, url),
+        };
+        let (host, path) = url.split_at(url.find('/').unwrap_or(url.len()));
+
+        let mut canonical = String::with_capacity(url.len() + 8);
+        if let Some(scheme) = scheme {
+            if self.https && scheme.eq_ignore_ascii_case("http") {
+                canonical.push_str("https");
+            } else if self.lowercase_host {
+                canonical.push_str(&scheme.to_ascii_lowercase());
+            } else {
+                canonical.push_str(scheme);
+            }
+            canonical.push_str("://");
+        }
+        let host = if self.lowercase_host {
+            host.to_ascii_lowercase()
+        } else {
+            host.to_string()
+        };
+        let host = match host.get(..4) {
+            Some(prefix) if self.strip_www && prefix.eq_ignore_ascii_case("www.") => &host[4..],
+            _ => &host,
+        };
+        canonical.push_str(host);
+        if self.strip_trailing_slash {
+            canonical.push_str(path.trim_end_matches('/'));
+        } else {
+            canonical.push_str(path);
+        }
+
+        if let Some(query) = query {
+            if self.strip_tracking_params {
+                let params: Vec<_> = query
+                    .split('&')
+                    .filter(|param| !is_tracking_param(param))
+                    .collect();
+                if !params.is_empty() {
+                    canonical.push('?');
+                    canonical.push_str(&params.join("&"));
+                }
+            } else {
+                canonical.push('?');
+                canonical.push_str(query);
+            }
+        }
+        if let Some(fragment) = fragment {
+            if !self.strip_fragment {
+                canonical.push('#');
+                canonical.push_str(fragment);
+            }
+        }
+        canonical
+    }
+}
+
+/// Split a string at the first occurence of a character, which is removed
+fn split_once(s: &str, c: char) -> (&str, Option<&str>) {
+    match s.find(c) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    }
+}
+
+/// Check if a query parameter is only used to track the clicks
+fn is_tracking_param(param: &str) -> bool {
+    let name = param.split('=').next().unwrap().to_ascii_lowercase();
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name.as_str())
+}
+
+/// Decode the html entities found in the urls of the dumps.
+/// Some urls were escaped several times, like `&amp;amp;`.
+fn decode_html_entities(url: &str) -> String {
+    let mut url = url.to_string();
+    while url.contains("&amp;") {
+        url = url.replace("&amp;", "&");
+    }
+    url.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Canonicalize the usual spellings of an url
+    #[test]
+    fn canonicalize_urls() {
+        let canonicalizer = UrlCanonicalizer::all();
+        let urls = [
+            "https://example.com/article",
+            "http://example.com/article",
+            "HTTP://WWW.Example.com/article/",
+            "https://www.example.com/article#comments",
+            "https://example.com/article?utm_source=reddit&utm_medium=social",
+            "https://example.com/article?fbclid=abc#top",
+        ];
+        for url in urls.iter() {
+            assert_eq!(canonicalizer.canonicalize(url), "https://example.com/article", "{}", url);
+        }
+        assert_eq!(
+            canonicalizer.canonicalize("http://www.youtube.com/watch?v=abc&amp;amp;feature=related&amp;utm_campaign=x"),
+            "https://youtube.com/watch?v=abc&feature=related"
+        );
+        assert_eq!(canonicalizer.canonicalize("http://example.com/"), "https://example.com");
+
+        // The path and the query are case sensitive
+        assert_eq!(canonicalizer.canonicalize("http://i.imgur.com/AbCd.jpg?X=1"), "https://i.imgur.com/AbCd.jpg?X=1");
+    }
+
+    /// Each rule can be disabled separately
+    #[test]
+    fn disable_rules() {
+        let url = "http://WWW.Example.com/a/?utm_source=x&amp;id=1#top";
+        assert_eq!(UrlCanonicalizer::default().canonicalize(url), url);
+        let expected = [
+            ("scheme", "http://example.com/a?id=1"),
+            ("host-case", "https://Example.com/a?id=1"),
+            ("www", "https://www.example.com/a?id=1"),
+            ("trailing-slash", "https://example.com/a/?id=1"),
+            ("entities", "https://example.com/a?amp;id=1"),
+            ("fragment", "https://example.com/a?id=1#top"),
+            ("tracking", "https://example.com/a?utm_source=x&id=1"),
+        ];
+        for (rule, expected) in expected.iter() {
+            let mut canonicalizer = UrlCanonicalizer::all();
+            canonicalizer.set_rule(rule, false).unwrap();
+            assert_eq!(&canonicalizer.canonicalize(url), expected, "{}", rule);
+        }
+        assert!(UrlCanonicalizer::all().set_rule("unknown", false).is_err());
+    }
+}
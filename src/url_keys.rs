@@ -0,0 +1,150 @@
+//! Get the keys grouping the posts linking to the same content
+
+use crate::domains::registrable_domain;
+use crate::media::{media_key, MediaExtractor, UrlParts};
+use crate::reddit_post::RedditPost;
+use crate::shorteners::ShortUrlTable;
+use crate::url_canonicalization::UrlCanonicalizer;
+use crate::url_rules::UrlRules;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// What the keys of the urls identify
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+    /// The canonical url
+    #[default]
+    Url,
+    /// The media linked by the url, for the urls of known media hosts
+    Media,
+    /// The registrable domain of the url
+    Domain,
+}
+
+/// Computes the keys under which the posts are grouped in `SubredditsFromUrls`.
+/// A post can have several keys, or none if it does not link to anything.
+#[derive(Debug, Clone, Default)]
+pub struct UrlKeyer {
+    /// Also use the links found in the text of self posts
+    pub selftext_urls: bool,
+    /// The expanded urls of the short urls, replacing them before the canonicalization
+    pub short_urls: ShortUrlTable,
+    /// The canonicalization applied to the urls before using them as keys
+    pub canonicalizer: UrlCanonicalizer,
+    /// The user-defined rules, applied in order on the canonical urls
+    pub url_rules: UrlRules,
+    pub granularity: Granularity,
+    /// With the media granularity, the urls linking to a media recognized
+    /// by one of these extractors are keyed by the media instead of the url
+    pub media_extractors: Vec<Arc<dyn MediaExtractor>>,
+    /// If given, the posts are grouped by these clusters of similar titles instead of their urls.
+    /// The posts that are not in a cluster have no key.
+    pub title_clusters: Option<HashMap<String, usize>>,
+}
+
+/// The steps of the computation of the key of an url
+#[derive(Debug)]
+pub struct UrlKeyTrace {
+    pub url: String,
+    /// The expanded url, if the url is a resolved short url
+    pub expanded: Option<String>,
+    pub canonical: String,
+    /// The rules that matched, with the url they produced
+    pub fired_rules: Vec<(String, String)>,
+    pub key: String,
+}
+
+impl fmt::Display for UrlKeyTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.url)?;
+        if let Some(expanded) = &self.expanded {
+            writeln!(f, "    expanded: {}", expanded)?;
+        }
+        writeln!(f, "    canonical: {}", self.canonical)?;
+        for (rule, url) in &self.fired_rules {
+            writeln!(f, "    rule {}: {}", rule, url)?;
+        }
+        writeln!(f, "    key: {}", self.key)
+    }
+}
+
+impl UrlKeyer {
+    /// Get the key of an url
+    pub fn url_key(&self, url: &str) -> String {
+        let url = self.short_urls.resolve(url).unwrap_or(url);
+        let url = self.canonicalizer.canonicalize(url);
+        let url = self.url_rules.apply(&url);
+        self.granular_key(url)
+    }
+
+    /// Get the key of an url, with the intermediate steps
+    pub fn trace_url_key(&self, url: &str) -> UrlKeyTrace {
+        let expanded = self.short_urls.resolve(url);
+        let canonical = self.canonicalizer.canonicalize(expanded.unwrap_or(url));
+        let (rewritten, fired_rules) = self.url_rules.apply_traced(&canonical);
+        let fired_rules = fired_rules
+            .into_iter()
+            .map(|(rule, url)| (rule.to_string(), url))
+            .collect();
+        UrlKeyTrace {
+            url: url.to_string(),
+            expanded: expanded.map(str::to_string),
+            canonical,
+            fired_rules,
+            key: self.granular_key(rewritten),
+        }
+    }
+
+    /// Get the key of an url with the granularity of the keyer
+    fn granular_key(&self, url: String) -> String {
+        match self.granularity {
+            Granularity::Url => url,
+            Granularity::Media => match media_key(&self.media_extractors, &url) {
+                Some(key) => key.to_string(),
+                None => url,
+            },
+            Granularity::Domain => match UrlParts::parse(&url).host.as_str() {
+                // The relative urls, like the urls of the crossposts, are reddit permalinks
+                "" => "reddit.com".to_string(),
+                host => registrable_domain(host),
+            },
+        }
+    }
+
+    /// Get the keys of a post
+    pub fn post_keys(&self, post: &RedditPost) -> Vec<String> {
+        if let Some(title_clusters) = &self.title_clusters {
+            return match title_clusters.get(&post.id) {
+                Some(cluster) => vec![format!("title:{}", cluster)],
+                None => vec![],
+            };
+        }
+        let mut keys: Vec<String> = Vec::new();
+        for url in post.get_linked_urls(self.selftext_urls) {
+            let key = self.url_key(&url);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key the relative urls by the reddit domain
+    #[test]
+    fn relative_url_domain() {
+        let keyer = UrlKeyer {
+            canonicalizer: UrlCanonicalizer::all(),
+            granularity: Granularity::Domain,
+            ..UrlKeyer::default()
+        };
+        assert_eq!(keyer.url_key("/r/funny/comments/e/t/"), "reddit.com");
+        assert_eq!(keyer.url_key("https://old.reddit.com/r/news/comments/z/t/"), "reddit.com");
+        assert_eq!(keyer.url_key("https://news.bbc.co.uk/article"), "bbc.co.uk");
+    }
+}
@@ -0,0 +1,102 @@
+//! User-defined rules rewriting the urls of a website into a common form,
+//! for the equivalences that are not handled by the canonicalization
+
+use regex::Regex;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+
+/// A rule, as written in the rules file
+#[derive(Deserialize)]
+struct UrlRuleSpec {
+    name: String,
+    pattern: String,
+    replacement: String,
+}
+
+/// A rule replacing the first match of a regex in an url.
+/// The replacement can use the groups of the regex, like `$1` or `${name}`.
+#[derive(Debug, Clone)]
+pub struct UrlRule {
+    pub name: String,
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+/// Rules applied in order on the urls
+#[derive(Debug, Clone, Default)]
+pub struct UrlRules {
+    pub rules: Vec<UrlRule>,
+}
+
+impl UrlRules {
+    /// Apply the rules on an url
+    pub fn apply(&self, url: &str) -> String {
+        let mut url = url.to_string();
+        for rule in &self.rules {
+            url = rule.pattern.replace(&url, rule.replacement.as_str()).into_owned();
+        }
+        url
+    }
+
+    /// Apply the rules on an url, and get the rules that matched with the url they produced
+    pub fn apply_traced(&self, url: &str) -> (String, Vec<(&str, String)>) {
+        let mut url = url.to_string();
+        let mut fired = Vec::new();
+        for rule in &self.rules {
+            if rule.pattern.is_match(&url) {
+                url = rule.pattern.replace(&url, rule.replacement.as_str()).into_owned();
+                fired.push((rule.name.as_str(), url.clone()));
+            }
+        }
+        (url, fired)
+    }
+}
+
+/// Load the rules from a JSON file containing a list of rules, like
+/// `[{"name": "amp", "pattern": "^(https://[^/]+)/amp/", "replacement": "$1/"}]`
+pub fn load_url_rules(filepath: &str) -> UrlRules {
+    let file = File::open(filepath).unwrap_or_else(|e| panic!("Error: cannot open the url rules file {}: {}", filepath, e));
+    let specs: Vec<UrlRuleSpec> = serde_json::from_reader(BufReader::new(file))
+        .unwrap_or_else(|e| panic!("Error: invalid url rules file {}: {}", filepath, e));
+    let rules = specs
+        .into_iter()
+        .map(|spec| UrlRule {
+            pattern: Regex::new(&spec.pattern)
+                .unwrap_or_else(|e| panic!("Error: invalid regex in url rule {}: {}", spec.name, e)),
+            name: spec.name,
+            replacement: spec.replacement,
+        })
+        .collect();
+    UrlRules { rules }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load rules from a file, and apply them in order
+    #[test]
+    fn apply_rules() {
+        let filepath = std::env::temp_dir().join("reddit_reposts_url_rules.json");
+        let data = r#"[
+            {"name": "amp", "pattern": "^(https://[^/]+)/amp/(.*)$", "replacement": "$1/$2"},
+            {"name": "mobile", "pattern": "^https://mobile\\.", "replacement": "https://"},
+            {"name": "bbc", "pattern": "^https://bbc\\.co\\.uk/", "replacement": "https://bbc.com/"}
+        ]"#;
+        std::fs::write(&filepath, data).unwrap();
+        let rules = load_url_rules(filepath.to_str().unwrap());
+        std::fs::remove_file(filepath).unwrap();
+
+        assert_eq!(rules.apply("https://example.com/amp/news/1"), "https://example.com/news/1");
+        assert_eq!(rules.apply("https://mobile.bbc.co.uk/amp/news"), "https://bbc.com/news");
+        assert_eq!(rules.apply("https://example.com/news/1"), "https://example.com/news/1");
+
+        let (url, fired) = rules.apply_traced("https://mobile.bbc.co.uk/news");
+        assert_eq!(url, "https://bbc.com/news");
+        assert_eq!(
+            fired,
+            vec![("mobile", "https://bbc.co.uk/news".to_string()), ("bbc", "https://bbc.com/news".to_string())]
+        );
+    }
+}
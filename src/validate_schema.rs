@@ -0,0 +1,254 @@
+//! Validate JSON objects against possible types saved as a schema
+
+use crate::possible_types::*;
+use crate::read_files::open_file;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::prelude::*;
+
+/// Difference between a JSON value and the schema
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SchemaIssue {
+    /// A field that is not in the schema
+    UnexpectedField,
+    /// A value with a type that is not in the schema
+    TypeChange(ValueType),
+    /// A null value, where the schema was never null nor missing
+    NewlyNull,
+    /// A missing field, where the schema was never null nor missing
+    NewlyMissing,
+}
+
+impl fmt::Display for SchemaIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaIssue::UnexpectedField => write!(f, "unexpected field"),
+            SchemaIssue::TypeChange(value_type) => write!(f, "type changed to {:?}", value_type),
+            SchemaIssue::NewlyNull => write!(f, "newly null"),
+            SchemaIssue::NewlyMissing => write!(f, "newly missing"),
+        }
+    }
+}
+
+/// Occurrences of an issue in the validated records
+#[derive(Debug, Clone, Default)]
+pub struct IssueOccurrences {
+    pub n_records: u64,
+    pub example_ids: Vec<String>,
+}
+
+/// Report of the validation of JSON objects against a schema
+#[derive(Debug, Clone)]
+pub struct ValidationReport {
+    pub n_records: u64,
+    pub n_invalid_lines: u64,
+    /// The issues found, associated to the path of the field
+    pub issues: BTreeMap<(String, SchemaIssue), IssueOccurrences>,
+    max_examples: usize,
+}
+
+impl ValidationReport {
+    /// Create an empty report, keeping at most `max_examples` record ids for each issue
+    pub fn new(max_examples: usize) -> Self {
+        ValidationReport {
+            n_records: 0,
+            n_invalid_lines: 0,
+            issues: BTreeMap::new(),
+            max_examples,
+        }
+    }
+
+    /// Check if the records are valid
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty() && self.n_invalid_lines == 0
+    }
+
+    /// Add an issue found in a record
+    fn add_issue(&mut self, path: &str, issue: SchemaIssue, record_id: &str) {
+        let occurrences = self.issues.entry((path.to_string(), issue)).or_default();
+        occurrences.n_records += 1;
+        if occurrences.example_ids.len() < self.max_examples {
+            occurrences.example_ids.push(record_id.to_string());
+        }
+    }
+
+    /// Validate a JSON object against the schema, and add the issues found to the report
+    pub fn validate_record(&mut self, schema: &PossibleTypes, record: &Value, line_number: u64) {
+        self.n_records += 1;
+        let record_id = match record.get("id").and_then(Value::as_str) {
+            Some(id) => id.to_string(),
+            None => format!("line {}", line_number),
+        };
+        self.validate_value(schema, false, record, "", &record_id);
+    }
+
+    /// Validate a value against its schema.
+    /// `nullable` is true if the value was null or missing in the schema.
+    fn validate_value(
+        &mut self,
+        schema: &PossibleTypes,
+        nullable: bool,
+        value: &Value,
+        path: &str,
+        record_id: &str,
+    ) {
+        let found_type = value_type(value);
+        if found_type == ValueType::Null {
+            if !nullable {
+                self.add_issue(path, SchemaIssue::NewlyNull, record_id);
+            }
+            return;
+        }
+        if schema.n_type(found_type) == 0 {
+            self.add_issue(path, SchemaIssue::TypeChange(found_type), record_id);
+            return;
+        }
+        match value {
+            Value::Object(map) => {
+                let n_objects = schema.n_type(ValueType::Object);
+                for (key, value) in map.iter() {
+                    let field_path = field_path(path, key);
+                    match schema.possible_fields.get(key) {
+                        Some(field) => {
+                            let nullable = field.is_nullable(n_objects);
+                            self.validate_value(field, nullable, value, &field_path, record_id);
+                        }
+                        None => self.add_issue(&field_path, SchemaIssue::UnexpectedField, record_id),
+                    }
+                }
+                for (key, field) in schema.possible_fields.iter() {
+                    if !map.contains_key(key) && !field.is_nullable(n_objects) {
+                        self.add_issue(&field_path(path, key), SchemaIssue::NewlyMissing, record_id);
+                    }
+                }
+            }
+            Value::Array(values) => {
+                let elements_path = format!("{}[]", path);
+                for value in values {
+                    match &schema.array_elements {
+                        Some(elements) => {
+                            let nullable = elements.n_type(ValueType::Null) > 0;
+                            self.validate_value(elements, nullable, value, &elements_path, record_id);
+                        }
+                        None => {
+                            let issue = SchemaIssue::TypeChange(value_type(value));
+                            self.add_issue(&elements_path, issue, record_id);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Get the path of a field, given the path of its parent
+fn field_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} records validated, {} invalid lines, {} issues",
+            self.n_records,
+            self.n_invalid_lines,
+            self.issues.len()
+        )?;
+        for ((path, issue), occurrences) in self.issues.iter() {
+            writeln!(
+                f,
+                "{}: {} in {} records (e.g. {})",
+                path,
+                issue,
+                occurrences.n_records,
+                occurrences.example_ids.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Validate the JSON objects stored in files against a schema.
+/// The files are expected to have one JSON object per line,
+/// and only the first `max_lines` lines of each file are read if given.
+pub fn validate_files(
+    schema: &PossibleTypes,
+    filepaths: &[&str],
+    max_lines: Option<usize>,
+    max_examples: usize,
+) -> ValidationReport {
+    let mut report = ValidationReport::new(max_examples);
+    for filepath in filepaths {
+        let lines = open_file(filepath).split(b'\n').take(max_lines.unwrap_or(usize::MAX));
+        for (i, line) in lines.enumerate() {
+            let line = line.unwrap();
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            match serde_json::from_slice::<Value>(&line) {
+                Ok(record) => report.validate_record(schema, &record, i as u64 + 1),
+                Err(_) => report.n_invalid_lines += 1,
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_against_saved_schema() {
+        let mut schema = PossibleTypes::new();
+        for line in [
+            r#"{"id":"a","score":1,"media":null,"tags":["x"]}"#,
+            r#"{"id":"b","score":2,"media":{"type":"v"},"tags":[],"edited":false}"#,
+        ]
+        .iter()
+        {
+            schema.add_type(serde_json::from_str(line).unwrap());
+        }
+        let filepath = std::env::temp_dir().join("reddit_reposts_schema.json");
+        let filepath = filepath.to_str().unwrap();
+        save_possible_types(&schema, filepath);
+        let schema = load_possible_types(filepath);
+        std::fs::remove_file(filepath).unwrap();
+
+        let mut report = ValidationReport::new(2);
+        for (i, line) in [
+            r#"{"id":"c","score":"3","media":{"type":"v","oembed":{}},"tags":[1]}"#,
+            r#"{"id":"d","score":null,"edited":true}"#,
+            r#"{"id":"e","score":"5","media":null,"tags":null}"#,
+        ]
+        .iter()
+        .enumerate()
+        {
+            report.validate_record(&schema, &serde_json::from_str(line).unwrap(), i as u64 + 1);
+        }
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|((path, issue), occurrences)| (path.as_str(), *issue, occurrences.example_ids.clone()))
+            .collect();
+        let ids = |ids: &[&str]| ids.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                ("media.oembed", SchemaIssue::UnexpectedField, ids(&["c"])),
+                ("score", SchemaIssue::TypeChange(ValueType::String), ids(&["c", "e"])),
+                ("score", SchemaIssue::NewlyNull, ids(&["d"])),
+                ("tags", SchemaIssue::NewlyNull, ids(&["e"])),
+                ("tags", SchemaIssue::NewlyMissing, ids(&["d"])),
+                ("tags[]", SchemaIssue::TypeChange(ValueType::Number), ids(&["c"])),
+            ]
+        );
+    }
+}